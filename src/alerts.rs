@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude::{ChannelId, CreateMessage, Http, MessageBuilder};
+
+use crate::config::{AlertRule, AlertsConfig};
+use crate::settings::Settings;
+
+pub struct Alert {
+    pub rule: AlertRule,
+    pub kind: AlertKind,
+    /// The TPS that caused the alert.
+    pub tps: f32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AlertKind {
+    Triggered,
+    Recovered,
+}
+
+#[derive(Default, Clone)]
+struct RuleState {
+    alerting: bool,
+    /// The number of consecutive polls for which the rule has been on the other side of its
+    /// threshold.
+    streak: u64,
+    /// Whether an alert was sent when the rule started alerting. If it was held back by the
+    /// cooldown, no recovery message is sent either.
+    notified: bool,
+    last_alert: Option<Instant>,
+}
+
+/// Keeps track of the alert rules of a single server.
+#[derive(Default)]
+pub struct AlertTracker {
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>,
+}
+
+impl AlertTracker {
+    /// Checks the TPS of a poll against each rule, and returns the alerts that should be sent. If
+    /// the rules changed since the last call, every rule starts over.
+    pub fn update(&mut self, config: &AlertsConfig, tps: &[f32; 5], now: Instant) -> Vec<Alert> {
+        if self.rules != config.rules {
+            self.rules = config.rules.clone();
+            self.states = vec![RuleState::default(); self.rules.len()];
+        }
+
+        let cooldown = Duration::from_secs(config.cooldown);
+        let mut alerts = vec![];
+
+        for (rule, state) in self.rules.iter().zip(&mut self.states) {
+            let tps = tps[rule.window.index()];
+
+            let crossed = if state.alerting {
+                tps >= rule.recover_above
+            } else {
+                tps < rule.below
+            };
+            state.streak = if crossed { state.streak + 1 } else { 0 };
+            if state.streak < rule.polls {
+                continue;
+            }
+
+            state.streak = 0;
+            state.alerting = !state.alerting;
+
+            if state.alerting {
+                state.notified = state
+                    .last_alert
+                    .is_none_or(|last| now.duration_since(last) >= cooldown);
+                if state.notified {
+                    state.last_alert = Some(now);
+                    alerts.push(Alert {
+                        rule: rule.clone(),
+                        kind: AlertKind::Triggered,
+                        tps,
+                    });
+                }
+            } else if std::mem::take(&mut state.notified) {
+                alerts.push(Alert {
+                    rule: rule.clone(),
+                    kind: AlertKind::Recovered,
+                    tps,
+                });
+            }
+        }
+
+        alerts
+    }
+}
+
+/// Posts `alerts` about `server` to the alerts channel, mentioning the op role.
+pub async fn post(http: &Http, settings: &Settings, server: &str, alerts: Vec<Alert>) {
+    let Some(config) = &settings.alerts else {
+        return;
+    };
+    let channel_id = ChannelId::new(config.channel_id);
+
+    for alert in alerts {
+        let rule = &alert.rule;
+        let window = rule.window.name();
+
+        let mut message = MessageBuilder::new();
+        match alert.kind {
+            AlertKind::Triggered => message
+                .role(settings.op_role_id)
+                .push(" ")
+                .push_bold_safe(server)
+                .push_safe(format!(
+                    " is lagging: the {window} TPS has been below {} for {} polls (it is now {:.2}).",
+                    rule.below, rule.polls, alert.tps
+                )),
+            AlertKind::Recovered => message.push_bold_safe(server).push_safe(format!(
+                " recovered: the {window} TPS is back to {:.2}.",
+                alert.tps
+            )),
+        };
+
+        if let Err(why) = channel_id
+            .send_message(http, CreateMessage::new().content(message.build()))
+            .await
+        {
+            eprintln!("Couldn't post alert to {channel_id}: {why}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TpsWindow;
+
+    fn config(cooldown: u64) -> AlertsConfig {
+        AlertsConfig {
+            channel_id: 1,
+            cooldown,
+            rules: vec![AlertRule {
+                window: TpsWindow::OneMinute,
+                below: 15.0,
+                recover_above: 18.0,
+                polls: 2,
+            }],
+        }
+    }
+
+    fn tps(value: f32) -> [f32; 5] {
+        [20.0, 20.0, value, 20.0, 20.0]
+    }
+
+    fn kinds(alerts: Vec<Alert>) -> Vec<AlertKind> {
+        alerts.into_iter().map(|alert| alert.kind).collect()
+    }
+
+    #[test]
+    fn triggers_and_recovers_with_hysteresis() {
+        let config = config(0);
+        let mut tracker = AlertTracker::default();
+        let now = Instant::now();
+
+        assert!(tracker.update(&config, &tps(10.0), now).is_empty());
+        assert_eq!(
+            kinds(tracker.update(&config, &tps(12.0), now)),
+            [AlertKind::Triggered]
+        );
+
+        // above the alert threshold, but not above the recovery one
+        assert!(tracker.update(&config, &tps(16.0), now).is_empty());
+        assert!(tracker.update(&config, &tps(16.0), now).is_empty());
+
+        assert!(tracker.update(&config, &tps(19.0), now).is_empty());
+        assert_eq!(
+            kinds(tracker.update(&config, &tps(19.5), now)),
+            [AlertKind::Recovered]
+        );
+    }
+
+    #[test]
+    fn streaks_must_be_consecutive() {
+        let config = config(0);
+        let mut tracker = AlertTracker::default();
+        let now = Instant::now();
+
+        for value in [10.0, 19.0, 10.0, 19.0] {
+            assert!(tracker.update(&config, &tps(value), now).is_empty());
+        }
+    }
+
+    #[test]
+    fn cooldown() {
+        let config = config(60);
+        let mut tracker = AlertTracker::default();
+        let start = Instant::now();
+
+        let mut poll = |value: f32, seconds: u64| {
+            kinds(tracker.update(&config, &tps(value), start + Duration::from_secs(seconds)))
+        };
+
+        poll(10.0, 0);
+        assert_eq!(poll(10.0, 5), [AlertKind::Triggered]);
+        poll(20.0, 10);
+        assert_eq!(poll(20.0, 15), [AlertKind::Recovered]);
+
+        // this one is held back by the cooldown, and so is its recovery
+        poll(10.0, 20);
+        assert!(poll(10.0, 25).is_empty());
+        poll(20.0, 30);
+        assert!(poll(20.0, 35).is_empty());
+
+        poll(10.0, 70);
+        assert_eq!(poll(10.0, 75), [AlertKind::Triggered]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Posts deaths and advancements from the server logs to each server's announcements channel.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude::{
+    ChannelId, Colour, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, CreateMessage, Http,
+    MessageBuilder,
+};
+use tokio::sync::broadcast;
+
+use crate::server_log::{AdvancementKind, LogEventKind};
+use crate::settings::Settings;
+use crate::Data;
+
+const DEATH_COLOUR: Colour = Colour::new(0x992d22);
+/// The colours the game uses for advancements.
+const ADVANCEMENT_COLOUR: Colour = Colour::new(0x55ff55);
+const CHALLENGE_COLOUR: Colour = Colour::new(0xaa00aa);
+
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// How often the announcements skipped by the rate limit are checked for a summary.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Limits the announcements of a single server to a number per minute, counting the ones over it.
+#[derive(Default)]
+struct RateLimiter {
+    sent: VecDeque<Instant>,
+    skipped: u64,
+}
+
+impl RateLimiter {
+    /// Returns whether another announcement can be posted, and if so counts it.
+    fn allow(&mut self, limit: u64, now: Instant) -> bool {
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= RATE_LIMIT_WINDOW)
+        {
+            self.sent.pop_front();
+        }
+
+        if (self.sent.len() as u64) < limit {
+            self.sent.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the number of skipped announcements once there's room to post a summary of them.
+    fn summary(&mut self, limit: u64, now: Instant) -> Option<u64> {
+        if self.skipped == 0 || !self.allow(limit, now) {
+            return None;
+        }
+        Some(std::mem::take(&mut self.skipped))
+    }
+}
+
+/// Posts the deaths and advancements from every server's log, for the servers with announcements
+/// enabled.
+pub async fn announce(data: Data, http: Arc<Http>) {
+    let mut events = data.log_events.subscribe();
+    let mut limiters: HashMap<Arc<str>, RateLimiter> = HashMap::new();
+    let mut interval = tokio::time::interval(SUMMARY_INTERVAL);
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = interval.tick() => {
+                post_summaries(&data, &http, &mut limiters).await;
+                continue;
+            }
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                log::warn!("Missed {count} log events while posting announcements");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let settings = data.settings();
+        let Some(config) = settings
+            .server(&event.server)
+            .and_then(|server| server.announcements.as_ref())
+        else {
+            continue;
+        };
+
+        let embed = match &event.kind {
+            LogEventKind::Death { player, message } if config.deaths => CreateEmbed::new()
+                .colour(DEATH_COLOUR)
+                .author(author(&settings, player))
+                .description(MessageBuilder::new().push_safe(message).build()),
+            LogEventKind::Advancement {
+                player,
+                kind,
+                title,
+            } if config.advancements => {
+                let (colour, verb) = match kind {
+                    AdvancementKind::Task => (ADVANCEMENT_COLOUR, "made the advancement"),
+                    AdvancementKind::Goal => (ADVANCEMENT_COLOUR, "reached the goal"),
+                    AdvancementKind::Challenge => (CHALLENGE_COLOUR, "completed the challenge"),
+                };
+                CreateEmbed::new()
+                    .colour(colour)
+                    .author(author(&settings, player))
+                    .description(
+                        MessageBuilder::new()
+                            .push_safe(format!("{player} has {verb} "))
+                            .push_bold_safe(format!("[{title}]"))
+                            .build(),
+                    )
+            }
+            _ => continue,
+        };
+
+        let limiter = limiters.entry(Arc::clone(&event.server)).or_default();
+        if !limiter.allow(config.max_per_minute, Instant::now()) {
+            limiter.skipped += 1;
+            continue;
+        }
+        post(
+            &http,
+            ChannelId::new(config.channel_id),
+            &event.server,
+            embed,
+        )
+        .await;
+    }
+}
+
+/// Posts how many announcements were skipped by the rate limits, once there's room for it.
+async fn post_summaries(data: &Data, http: &Http, limiters: &mut HashMap<Arc<str>, RateLimiter>) {
+    let settings = data.settings();
+    for (server, limiter) in limiters {
+        let Some(config) = settings
+            .server(server)
+            .and_then(|server| server.announcements.as_ref())
+        else {
+            continue;
+        };
+        let Some(skipped) = limiter.summary(config.max_per_minute, Instant::now()) else {
+            continue;
+        };
+
+        let embed = CreateEmbed::new().description(format!(
+            "...and {skipped} more {}.",
+            if skipped == 1 {
+                "announcement"
+            } else {
+                "announcements"
+            }
+        ));
+        post(http, ChannelId::new(config.channel_id), server, embed).await;
+    }
+}
+
+/// Shows the player, along with their head if the list's head URL doesn't need their UUID (which
+/// the log doesn't have).
+fn author(settings: &Settings, player: &str) -> CreateEmbedAuthor {
+    let author = CreateEmbedAuthor::new(player);
+    match &settings.list.head_url {
+        Some(template) if !template.contains("{uuid}") => {
+            author.icon_url(template.replace("{name}", player))
+        }
+        _ => author,
+    }
+}
+
+async fn post(http: &Http, channel_id: ChannelId, server: &str, embed: CreateEmbed) {
+    let embed = embed.footer(CreateEmbedFooter::new(server));
+    if let Err(why) = channel_id
+        .send_message(http, CreateMessage::new().embed(embed))
+        .await
+    {
+        eprintln!("Couldn't post announcement to {channel_id}: {why}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::default();
+
+        assert!(limiter.allow(2, start));
+        assert!(limiter.allow(2, start + Duration::from_secs(10)));
+        assert!(!limiter.allow(2, start + Duration::from_secs(20)));
+        limiter.skipped += 1;
+        assert_eq!(limiter.summary(2, start + Duration::from_secs(30)), None);
+
+        // the first announcement is out of the window, which makes room for the summary
+        assert_eq!(limiter.summary(2, start + Duration::from_secs(60)), Some(1));
+        assert_eq!(limiter.summary(2, start + Duration::from_secs(200)), None);
+        assert!(limiter.allow(2, start + Duration::from_secs(200)));
+    }
+}
@@ -0,0 +1,239 @@
+//! The audit log of the console commands run through `/run`.
+
+use poise::serenity_prelude::{
+    ChannelId, CreateAllowedMentions, CreateMessage, Http, MessageBuilder, UserId,
+};
+use rusqlite::params;
+
+use crate::commands::truncate;
+use crate::store::Store;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS run_audit (
+    id INTEGER PRIMARY KEY,
+    time INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    user_name TEXT NOT NULL,
+    -- NULL if the command was run outside of a guild
+    guild_id INTEGER,
+    server TEXT NOT NULL,
+    command TEXT NOT NULL,
+    -- the server's response, or what went wrong
+    response TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS run_audit_time ON run_audit (time);
+";
+
+/// How much of a response is kept.
+const MAX_RESPONSE_LENGTH: usize = 1000;
+
+/// How much of a command [`describe`] shows. Commands are kept whole, but can be long enough
+/// (along with the response) to go over discord's message length limit.
+const MAX_SHOWN_COMMAND_LENGTH: usize = 500;
+
+pub struct AuditEntry {
+    pub time: i64,
+    pub user_id: u64,
+    pub user_name: String,
+    pub guild_id: Option<u64>,
+    pub server: String,
+    pub command: String,
+    pub response: String,
+}
+
+/// Which entries [`search`] returns. Every condition that is set must match.
+#[derive(Default)]
+pub struct Filter {
+    pub user_id: Option<u64>,
+    /// Matches commands containing this (case-insensitively).
+    pub contains: Option<String>,
+    /// The earliest unix time to include.
+    pub since: Option<i64>,
+}
+
+/// Records `entry` in the audit log (truncating the response), and posts it to `channel_id`.
+pub async fn record(
+    store: &Store,
+    http: &Http,
+    channel_id: Option<ChannelId>,
+    mut entry: AuditEntry,
+) -> rusqlite::Result<()> {
+    entry.response = truncate(&entry.response, MAX_RESPONSE_LENGTH);
+
+    if let Some(channel_id) = channel_id {
+        let message = CreateMessage::new()
+            .content(describe(&entry))
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(why) = channel_id.send_message(http, message).await {
+            eprintln!("Couldn't post to the audit channel {channel_id}: {why}");
+        }
+    }
+
+    store
+        .call(move |conn| {
+            conn.execute(
+                "INSERT INTO run_audit (time, user_id, user_name, guild_id, server, command, response)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                // discord ids fit in an i64, which is what sqlite stores integers as
+                params![
+                    entry.time,
+                    entry.user_id as i64,
+                    entry.user_name,
+                    entry.guild_id.map(|id| id as i64),
+                    entry.server,
+                    entry.command,
+                    entry.response
+                ],
+            )
+            .map(|_| ())
+        })
+        .await
+}
+
+/// Returns the latest `limit` entries matching `filter`, newest first.
+pub async fn search(
+    store: &Store,
+    filter: Filter,
+    limit: usize,
+) -> rusqlite::Result<Vec<AuditEntry>> {
+    // instr is used instead of LIKE, so that % and _ in the search aren't treated as wildcards
+    store
+        .call(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT time, user_id, user_name, guild_id, server, command, response FROM run_audit
+                WHERE (?1 IS NULL OR user_id = ?1)
+                AND (?2 IS NULL OR instr(lower(command), lower(?2)) > 0)
+                AND (?3 IS NULL OR time >= ?3)
+                ORDER BY time DESC, id DESC
+                LIMIT ?4",
+            )?;
+
+            let rows = statement.query_map(
+                params![
+                    filter.user_id.map(|id| id as i64),
+                    filter.contains,
+                    filter.since,
+                    limit as i64
+                ],
+                |row| {
+                    Ok(AuditEntry {
+                        time: row.get(0)?,
+                        user_id: row.get::<_, i64>(1)? as u64,
+                        user_name: row.get(2)?,
+                        guild_id: row.get::<_, Option<i64>>(3)?.map(|id| id as u64),
+                        server: row.get(4)?,
+                        command: row.get(5)?,
+                        response: row.get(6)?,
+                    })
+                },
+            )?;
+            rows.collect()
+        })
+        .await
+}
+
+/// Formats an entry as a discord message. This mentions the user, so it should be sent without
+/// allowing any mentions.
+pub fn describe(entry: &AuditEntry) -> String {
+    MessageBuilder::new()
+        .push(format!("<t:{}:f> ", entry.time))
+        .mention(&UserId::new(entry.user_id))
+        .push_safe(format!(" ({}) ran on {}: ", entry.user_name, entry.server))
+        .push_mono_safe(truncate(&entry.command, MAX_SHOWN_COMMAND_LENGTH))
+        .push_codeblock_safe(&entry.response, None)
+        .build()
+}
+
+/// Parses a duration such as `30m`, `12h`, `7d` or `2w` into seconds.
+pub fn parse_duration(duration: &str) -> Option<i64> {
+    let duration = duration.trim();
+    let split = duration.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = duration.split_at(split);
+    let amount: i64 = amount.parse().ok()?;
+
+    let unit = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => return None,
+    };
+    amount.checked_mul(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::MAX_MESSAGE_LENGTH;
+    use crate::interface::MAX_COMMAND_LENGTH;
+
+    fn entry(time: i64, user_id: u64, command: &str) -> AuditEntry {
+        AuditEntry {
+            time,
+            user_id,
+            user_name: format!("user{user_id}"),
+            guild_id: Some(1),
+            server: "test".into(),
+            command: command.into(),
+            response: "ok".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn search_filters() {
+        let store = Store::open(":memory:", &[SCHEMA]).unwrap();
+        let http = Http::new("");
+        for entry in [
+            entry(100, 1, "whitelist add alex"),
+            entry(200, 2, "op steve"),
+            entry(300, 1, "OP notch"),
+            entry(400, 2, "say 100%_done"),
+        ] {
+            record(&store, &http, None, entry).await.unwrap();
+        }
+
+        let times = |entries: Vec<AuditEntry>| -> Vec<i64> {
+            entries.into_iter().map(|entry| entry.time).collect()
+        };
+
+        let all = search(&store, Filter::default(), 10).await.unwrap();
+        assert_eq!(times(all), [400, 300, 200, 100]);
+
+        let filter = Filter {
+            contains: Some("op ".into()),
+            ..Filter::default()
+        };
+        assert_eq!(times(search(&store, filter, 10).await.unwrap()), [300, 200]);
+
+        let filter = Filter {
+            user_id: Some(1),
+            since: Some(150),
+            ..Filter::default()
+        };
+        assert_eq!(times(search(&store, filter, 10).await.unwrap()), [300]);
+
+        let filter = Filter {
+            contains: Some("%_".into()),
+            ..Filter::default()
+        };
+        assert_eq!(times(search(&store, filter, 10).await.unwrap()), [400]);
+    }
+
+    #[test]
+    fn long_entries_fit_in_a_message() {
+        let mut entry = entry(100, 1, &"a".repeat(MAX_COMMAND_LENGTH));
+        entry.response = truncate(&"b".repeat(5000), MAX_RESPONSE_LENGTH);
+        assert!(describe(&entry).len() <= MAX_MESSAGE_LENGTH);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30m"), Some(30 * 60));
+        assert_eq!(parse_duration(" 2d "), Some(2 * 60 * 60 * 24));
+        assert_eq!(parse_duration("1w"), Some(60 * 60 * 24 * 7));
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("12"), None);
+        assert_eq!(parse_duration("5y"), None);
+    }
+}
@@ -0,0 +1,383 @@
+//! A small rasterizer for line charts, which are encoded as PNG.
+
+const WIDTH: usize = 800;
+const PANEL_HEIGHT: usize = 170;
+/// The space on the left of each panel, where the y axis labels go.
+const MARGIN_LEFT: usize = 48;
+const MARGIN_RIGHT: usize = 16;
+/// The space above each panel, where its title goes.
+const MARGIN_TOP: usize = 26;
+/// The space below the last panel, where the x axis labels go.
+const MARGIN_BOTTOM: usize = 24;
+
+const BACKGROUND: Rgb = [0x2b, 0x2d, 0x31];
+const GRID: Rgb = [0x44, 0x47, 0x4d];
+const TEXT: Rgb = [0xdb, 0xde, 0xe1];
+
+/// How many intervals the axes are divided into.
+const Y_DIVISIONS: usize = 4;
+const X_DIVISIONS: usize = 6;
+
+pub type Rgb = [u8; 3];
+
+/// A chart with a shared time axis and one panel per quantity.
+pub struct Chart<'a> {
+    pub panels: Vec<Panel<'a>>,
+    /// The unix time at the left edge of the chart.
+    pub start: i64,
+    /// The unix time at the right edge of the chart.
+    pub end: i64,
+    /// Consecutive points further apart than this (in seconds) aren't connected.
+    pub max_gap: i64,
+    pub time_format: TimeFormat,
+}
+
+pub struct Panel<'a> {
+    pub title: &'a str,
+    pub colour: Rgb,
+    /// `(unix time, value)` pairs, in chronological order.
+    pub points: Vec<(i64, f32)>,
+    /// The bottom of the y axis.
+    pub min: f32,
+    /// The top of the y axis. It is raised to fit the highest point if needed, and rounded up so
+    /// the grid lines land on round numbers.
+    pub max: f32,
+}
+
+#[derive(Clone, Copy)]
+pub enum TimeFormat {
+    /// `HH:MM`, in UTC.
+    Time,
+    /// `MM-DD`, in UTC.
+    Date,
+}
+
+impl Chart<'_> {
+    /// Draws the chart and returns it as a PNG image.
+    pub fn render(&self) -> Vec<u8> {
+        let height = self.panels.len() * (PANEL_HEIGHT + MARGIN_TOP) + MARGIN_BOTTOM;
+        let mut canvas = Canvas::new(WIDTH, height);
+        let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+        let duration = (self.end - self.start).max(1) as f32;
+        let x_of = |time: i64| {
+            MARGIN_LEFT as f32 + (time - self.start) as f32 / duration * plot_width as f32
+        };
+
+        for (i, panel) in self.panels.iter().enumerate() {
+            let top = i * (PANEL_HEIGHT + MARGIN_TOP) + MARGIN_TOP;
+            let bottom = top + PANEL_HEIGHT;
+            let max = axis_max(panel);
+            let y_of = |value: f32| {
+                let value = value.clamp(panel.min, max);
+                bottom as f32 - (value - panel.min) / (max - panel.min) * PANEL_HEIGHT as f32
+            };
+
+            canvas.text(MARGIN_LEFT, top - 20, panel.title, panel.colour, 2);
+
+            for division in 0..=Y_DIVISIONS {
+                let value = panel.min + (max - panel.min) * division as f32 / Y_DIVISIONS as f32;
+                let y = y_of(value).round() as usize;
+                canvas.hline(MARGIN_LEFT, WIDTH - MARGIN_RIGHT, y.min(bottom), GRID);
+
+                let label = format_value(value);
+                let label_x = MARGIN_LEFT - 6 - text_width(&label, 1);
+                canvas.text(label_x, y.saturating_sub(3), &label, TEXT, 1);
+            }
+
+            for division in 0..=X_DIVISIONS {
+                let x = MARGIN_LEFT + plot_width * division / X_DIVISIONS;
+                canvas.vline(x, top, bottom, GRID);
+            }
+
+            for pair in panel.points.windows(2) {
+                let ((t0, v0), (t1, v1)) = (pair[0], pair[1]);
+                if t1 - t0 > self.max_gap {
+                    continue;
+                }
+                canvas.line(x_of(t0), y_of(v0), x_of(t1), y_of(v1), panel.colour);
+            }
+
+            // points without a neighbor would be invisible otherwise
+            for (i, &(time, value)) in panel.points.iter().enumerate() {
+                let connected = |other: Option<&(i64, f32)>| {
+                    other.is_some_and(|&(other, _)| (other - time).abs() <= self.max_gap)
+                };
+                if !connected(i.checked_sub(1).and_then(|i| panel.points.get(i)))
+                    && !connected(panel.points.get(i + 1))
+                {
+                    canvas.dot(x_of(time), y_of(value), panel.colour);
+                }
+            }
+        }
+
+        let label_y = height - MARGIN_BOTTOM + 8;
+        for division in 0..=X_DIVISIONS {
+            let time = self.start + (self.end - self.start) * division as i64 / X_DIVISIONS as i64;
+            let label = self.time_format.format(time);
+            let x = MARGIN_LEFT + plot_width * division / X_DIVISIONS;
+            let x = x.saturating_sub(text_width(&label, 1) / 2);
+            canvas.text(
+                x.min(WIDTH - text_width(&label, 1)),
+                label_y,
+                &label,
+                TEXT,
+                1,
+            );
+        }
+        canvas.text(4, label_y, "UTC", TEXT, 1);
+
+        canvas.encode()
+    }
+}
+
+impl TimeFormat {
+    fn format(self, time: i64) -> String {
+        let days = time.div_euclid(86400);
+        let seconds = time.rem_euclid(86400);
+        match self {
+            TimeFormat::Time => format!("{:02}:{:02}", seconds / 3600, seconds / 60 % 60),
+            TimeFormat::Date => {
+                let (month, day) = month_and_day(days);
+                format!("{month:02}-{day:02}")
+            }
+        }
+    }
+}
+
+/// Converts a number of days since the unix epoch to a month and day, using the proleptic
+/// gregorian calendar. See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn month_and_day(days: i64) -> (i64, i64) {
+    let days = days + 719468;
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    (month, day)
+}
+
+fn axis_max(panel: &Panel) -> f32 {
+    let highest = panel
+        .points
+        .iter()
+        .map(|&(_, value)| value)
+        .fold(panel.max, f32::max);
+    if highest <= panel.max {
+        return panel.max;
+    }
+
+    // the smallest "nice" step (1, 2 or 5 times a power of 10) that fits everything
+    let raw_step = (highest - panel.min) / Y_DIVISIONS as f32;
+    let magnitude = 10f32.powf(raw_step.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|&step| step >= raw_step)
+        .unwrap();
+    panel.min + step * Y_DIVISIONS as f32
+}
+
+fn format_value(value: f32) -> String {
+    if value.fract() == 0.0 {
+        format!("{value:.0}")
+    } else {
+        format!("{value:.1}")
+    }
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: BACKGROUND.repeat(width * height),
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, colour: Rgb) {
+        if x < self.width && y < self.height {
+            let i = (y * self.width + x) * 3;
+            self.pixels[i..i + 3].copy_from_slice(&colour);
+        }
+    }
+
+    fn hline(&mut self, x0: usize, x1: usize, y: usize, colour: Rgb) {
+        for x in x0..=x1 {
+            self.set(x, y, colour);
+        }
+    }
+
+    fn vline(&mut self, x: usize, y0: usize, y1: usize, colour: Rgb) {
+        for y in y0..=y1 {
+            self.set(x, y, colour);
+        }
+    }
+
+    /// A 2x2 square centered on the point.
+    fn dot(&mut self, x: f32, y: f32, colour: Rgb) {
+        let (x, y) = (x.round() as i64, y.round() as i64);
+        for (dx, dy) in [(-1, -1), (0, -1), (-1, 0), (0, 0)] {
+            if let (Ok(x), Ok(y)) = (usize::try_from(x + dx), usize::try_from(y + dy)) {
+                self.set(x, y, colour);
+            }
+        }
+    }
+
+    /// A 2 pixel wide line, drawn by stepping along its longest axis.
+    fn line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, colour: Rgb) {
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0) as usize;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            self.dot(x0 + (x1 - x0) * t, y0 + (y1 - y0) * t, colour);
+        }
+    }
+
+    /// Draws `text` with its top left corner at `(x, y)`, with each font pixel being `scale`
+    /// pixels wide.
+    fn text(&mut self, x: usize, y: usize, text: &str, colour: Rgb, scale: usize) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph = glyph(c);
+            let left = x + i * GLYPH_ADVANCE * scale;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            self.set(left + column * scale + dx, y + row * scale + dy, colour);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut output = vec![];
+        let mut encoder = png::Encoder::new(&mut output, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        // writing to a vec can't fail, and the dimensions are always valid
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.pixels).unwrap();
+        writer.finish().unwrap();
+
+        output
+    }
+}
+
+const GLYPH_WIDTH: usize = 5;
+/// The glyph width plus the space between glyphs.
+const GLYPH_ADVANCE: usize = GLYPH_WIDTH + 1;
+
+fn text_width(text: &str, scale: usize) -> usize {
+    (text.chars().count() * GLYPH_ADVANCE).saturating_sub(1) * scale
+}
+
+/// Returns the rows of a 5x7 glyph, with the leftmost pixel in the 5th bit. Letters are always
+/// uppercase, and unknown characters are blank.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        'A' => [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        _ => [0; 7],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(TimeFormat::Date.format(0), "01-01");
+        // 2024-02-29 12:34:56
+        assert_eq!(TimeFormat::Date.format(1709210096), "02-29");
+        assert_eq!(TimeFormat::Time.format(1709210096), "12:34");
+    }
+
+    #[test]
+    fn axis_is_raised_to_fit() {
+        let mut panel = Panel {
+            title: "PLAYERS",
+            colour: TEXT,
+            points: vec![(0, 3.0)],
+            min: 0.0,
+            max: 4.0,
+        };
+        assert_eq!(axis_max(&panel), 4.0);
+
+        panel.points.push((1, 37.0));
+        assert_eq!(axis_max(&panel), 40.0);
+    }
+
+    #[test]
+    fn renders_a_png() {
+        let chart = Chart {
+            panels: vec![Panel {
+                title: "TPS",
+                colour: [0x57, 0xf2, 0x87],
+                points: vec![(0, 20.0), (60, 12.5), (120, 19.0), (600, 20.0)],
+                min: 0.0,
+                max: 20.0,
+            }],
+            start: 0,
+            end: 3600,
+            max_gap: 120,
+            time_format: TimeFormat::Time,
+        };
+
+        let png = chart.render();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+}
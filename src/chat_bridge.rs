@@ -0,0 +1,168 @@
+//! Relays chat between each server and its chat channel.
+
+use std::sync::Arc;
+
+use poise::serenity_prelude::{
+    self as serenity, Colour, CreateAllowedMentions, CreateMessage, Http, Message, MessageBuilder,
+};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+use crate::formatting::Text;
+use crate::interface::MAX_COMMAND_LENGTH;
+use crate::server_log::LogEventKind;
+use crate::Data;
+
+/// Posts the chat messages from every server's log to its chat channel, if it has one.
+pub async fn relay_to_discord(data: Data, http: Arc<Http>) {
+    let mut events = data.log_events.subscribe();
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                log::warn!("Missed {count} log events while relaying chat to discord");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let LogEventKind::Chat { player, message } = &event.kind else {
+            continue;
+        };
+        let settings = data.settings();
+        let Some(channel_id) = settings
+            .server(&event.server)
+            .and_then(|server| server.chat_channel_id)
+        else {
+            continue;
+        };
+
+        let content = MessageBuilder::new()
+            .push_bold_safe(player)
+            .push(": ")
+            .push_safe(message)
+            .build();
+        let message = CreateMessage::new()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(why) = channel_id.send_message(&http, message).await {
+            eprintln!("Couldn't relay chat to {channel_id}: {why}");
+        }
+    }
+}
+
+/// Shows `message` in the game, if it was sent to a chat channel.
+pub async fn relay_to_minecraft(ctx: &serenity::Context, data: &Data, message: &Message) {
+    // this also keeps the messages relayed by `relay_to_discord` from coming back
+    if message.author.bot || message.webhook_id.is_some() {
+        return;
+    }
+    let settings = data.settings();
+    let Some(server) = settings
+        .servers
+        .iter()
+        .find(|server| server.chat_channel_id == Some(message.channel_id))
+    else {
+        return;
+    };
+
+    let member = message.member(ctx).await.ok();
+    let name = match &member {
+        Some(member) => member.display_name(),
+        None => (message.author.global_name.as_deref()).unwrap_or(&message.author.name),
+    };
+    let colour = member.as_ref().and_then(|member| member.colour(&ctx.cache));
+
+    let mut content = message.content_safe(&ctx.cache);
+    if !message.attachments.is_empty() {
+        content = format!("{content} [attachment]").trim_start().to_owned();
+    }
+    if content.trim().is_empty() {
+        return;
+    }
+
+    let command = tellraw(name, colour, &content);
+    // if this fails, the server is offline and there's nobody to show the message to anyway
+    let result = server.interface.lock().await.exec(&command).await;
+    if let Err(why) = result {
+        log::warn!("Couldn't relay a message to {}: {why}", server.name);
+    }
+}
+
+/// Builds a `tellraw` command which shows `content` as a chat message from `name`. Both of them
+/// end up in JSON strings of plain text components (which don't resolve selectors), with
+/// formatting codes and tags removed, so nothing can be injected. Long messages are cut off to keep the
+/// command within the RCON limit.
+fn tellraw(name: &str, colour: Option<Colour>, content: &str) -> String {
+    let mut author = json!({ "text": Text::parse(name).plain() });
+    if let Some(colour) = colour {
+        author["color"] = Value::String(format!("#{}", colour.hex()));
+    }
+
+    let mut content: Vec<char> = Text::parse(content).plain().chars().collect();
+    let mut truncated = false;
+    loop {
+        let mut text: String = content.iter().collect();
+        if truncated {
+            text.push('…');
+        }
+        let component = json!([
+            "",
+            { "text": "[Discord] ", "color": "blue" },
+            "<",
+            author,
+            "> ",
+            { "text": text },
+        ]);
+        let command = format!("tellraw @a {component}");
+
+        if command.len() <= MAX_COMMAND_LENGTH || content.is_empty() {
+            return command;
+        }
+        let mut excess = command.len() - MAX_COMMAND_LENGTH;
+        if !truncated {
+            excess += '…'.len_utf8();
+            truncated = true;
+        }
+        while excess > 0 {
+            let Some(character) = content.pop() else {
+                break;
+            };
+            // escaped, as it is in the command
+            let length = serde_json::to_string(&character).unwrap().len() - 2;
+            excess = excess.saturating_sub(length);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            tellraw("Alex", Some(Colour::new(0x1abc9c)), "hi @a"),
+            r##"tellraw @a ["",{"color":"blue","text":"[Discord] "},"<",{"color":"#1ABC9C","text":"Alex"},"> ",{"text":"hi @a"}]"##
+        );
+
+        let command = tellraw(r#"a"},{"selector":"@e"#, None, "§4\"}]\n");
+        let component: Value =
+            serde_json::from_str(command.strip_prefix("tellraw @a ").unwrap()).unwrap();
+        assert_eq!(component[3]["text"], r#"a"},{"selector":"@e"#);
+        assert_eq!(component[5]["text"], "\"}]\n");
+
+        let command = tellraw("§cAlex", None, "§4Hello <b>there</b> <3");
+        assert!(command.ends_with(r#""<",{"text":"Alex"},"> ",{"text":"Hello there <3"}]"#));
+    }
+
+    #[test]
+    fn long_messages_are_cut_off() {
+        for content in ["a".repeat(2000), "\"".repeat(2000), "é".repeat(2000)] {
+            let command = tellraw("Alex", None, &content);
+            assert!(command.len() <= MAX_COMMAND_LENGTH);
+            assert!(command.len() > MAX_COMMAND_LENGTH - 10);
+            assert!(command.ends_with("…\"}]"));
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use poise::serenity_prelude::{CreateAllowedMentions, User};
+use poise::CreateReply;
+
+use super::MAX_MESSAGE_LENGTH;
+use crate::audit::{self, Filter};
+use crate::playtime::now;
+use crate::{Context, Error};
+
+/// How many entries are shown by `/audit search`.
+const SEARCH_LIMIT: usize = 10;
+
+#[poise::command(slash_command, subcommands("search"))]
+pub async fn audit(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Search the log of commands run through /run.
+#[poise::command(slash_command, guild_only)]
+async fn search(
+    ctx: Context<'_>,
+    #[description = "Only show commands run by this user."] user: Option<User>,
+    #[description = "Only show commands containing this text."] contains: Option<String>,
+    #[description = "Only show commands run in this period, e.g. 30m, 12h, 7d or 2w."]
+    since: Option<String>,
+) -> Result<(), Error> {
+    let since = match since {
+        Some(since) => match audit::parse_duration(&since) {
+            Some(duration) => Some(now() - duration),
+            None => {
+                ctx.say("Invalid period (use e.g. 30m, 12h, 7d or 2w).")
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let filter = Filter {
+        user_id: user.map(|user| user.id.get()),
+        contains,
+        since,
+    };
+    let entries = audit::search(&ctx.data().store, filter, SEARCH_LIMIT).await?;
+    if entries.is_empty() {
+        ctx.say("No matching commands were found.").await?;
+        return Ok(());
+    }
+
+    // newest first, leaving out whatever doesn't fit
+    let mut result = String::new();
+    for entry in &entries {
+        let description = audit::describe(entry);
+        if !result.is_empty() && result.len() + description.len() + 1 > MAX_MESSAGE_LENGTH {
+            break;
+        }
+        result += &description;
+        result += "\n";
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .content(result)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -5,12 +5,19 @@ use poise::serenity_prelude::CreateAttachment;
 use poise::CreateReply;
 use tokio::fs;
 
+use crate::config::Tier;
 use crate::{Context, Error};
 
 /// Upload the latest crash report.
-#[poise::command(slash_command, global_cooldown = 30)]
-pub async fn crash(ctx: Context<'_>) -> Result<(), Error> {
-    let mut path = PathBuf::from(&*ctx.data().server_directory);
+#[poise::command(slash_command, global_cooldown = 30, custom_data = "Tier::Moderator")]
+pub async fn crash(
+    ctx: Context<'_>,
+    #[description = "The server whose crash report to upload."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    let mut path = PathBuf::from(&*server.directory);
     path.push("crash-reports");
 
     let (file, created) = get_latest_file(path).await?;
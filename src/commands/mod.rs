@@ -1,31 +1,141 @@
+mod audit;
 mod crash;
+mod moderation;
+mod perf;
+mod permissions;
+mod playtime;
 mod run;
 mod schedule_restart;
+mod server;
 mod source;
 mod user_db;
 mod whitelist;
 
+pub use audit::audit;
 pub use crash::crash;
+pub use moderation::moderation;
+pub use perf::perf;
+pub use permissions::permissions;
+pub use playtime::playtime;
 pub use run::run;
 pub use schedule_restart::schedule_restart;
+pub use server::server;
 pub use source::source;
 pub use user_db::user_db;
 use uuid_mc::PlayerUuid;
 pub use whitelist::whitelist;
 
+use std::sync::Arc;
+
+use crate::config::Tier;
+use crate::permissions::{Denial, Invoker};
+use crate::server::Server;
 use crate::Error;
 
-async fn operator_only(ctx: crate::Context<'_>) -> Result<bool, crate::Error> {
-    let Some(member) = ctx.author_member().await else {
-        return Ok(false);
+/// The maximum length of a discord message.
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// Cuts `text` down to at most `max` bytes, ending it with "..." if anything was cut off.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_owned();
+    }
+
+    let mut end = max - 3;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
+
+/// Keeps `text` from ending a code block early.
+pub fn escape_code_block(text: &str) -> String {
+    text.replace("```", "``\u{200b}`")
+}
+
+/// Checks the invoker's permissions for the command (see [`crate::permissions`]). This runs before
+/// every command.
+pub async fn check_permissions(ctx: crate::Context<'_>) -> Result<bool, crate::Error> {
+    let settings = ctx.data().settings();
+    let roles = match ctx.author_member().await {
+        Some(member) => member.roles.clone(),
+        None => vec![],
+    };
+    let invoker = Invoker {
+        user_id: ctx.author().id,
+        roles: &roles,
+        tier: settings.permissions.tier(settings.op_role_id, &roles),
+        channel_id: ctx.channel_id(),
     };
 
-    if member.roles.contains(&ctx.data().op_role_id) {
-        Ok(true)
-    } else {
-        ctx.say("You're not an op!").await?;
-        Ok(false)
+    let command = ctx.command();
+    let mut tier = default_tier(command);
+    // the run policies grant `/run` to their roles, and then limit what those may run with it
+    if command.qualified_name == "run" && settings.run.has_policy(&roles) {
+        tier = Tier::Member;
     }
+    match settings
+        .permissions
+        .check(&command.qualified_name, tier, &invoker)
+    {
+        Ok(()) => Ok(true),
+        Err(Denial::Channel) => {
+            ctx.say("This command can't be used in this channel.")
+                .await?;
+            Ok(false)
+        }
+        Err(Denial::Tier(Tier::Admin)) => {
+            ctx.say("You're not an op!").await?;
+            Ok(false)
+        }
+        Err(Denial::Tier(tier)) => {
+            ctx.say(format!("This command is only for {}s.", tier.name()))
+                .await?;
+            Ok(false)
+        }
+    }
+}
+
+/// Returns the tier a command requires unless the config says otherwise, which is set using
+/// `custom_data`. Commands without one are for admins only.
+fn default_tier(command: &poise::Command<crate::Data, crate::Error>) -> Tier {
+    command
+        .custom_data
+        .downcast_ref::<Tier>()
+        .copied()
+        .unwrap_or(Tier::Admin)
+}
+
+async fn autocomplete_server<'a>(
+    ctx: crate::Context<'a>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    let settings = ctx.data().settings();
+    let names: Vec<String> = settings
+        .servers
+        .iter()
+        .map(|server| server.name.to_string())
+        .collect();
+
+    names
+        .into_iter()
+        .filter(move |name| name.starts_with(partial))
+}
+
+/// Finds the server with the given name. If no name is given, the only configured server is
+/// returned, if there is exactly one.
+fn get_server(ctx: crate::Context<'_>, name: Option<&str>) -> Result<Arc<Server>, Error> {
+    let settings = ctx.data().settings();
+    let servers = &settings.servers;
+    let server = match name {
+        Some(name) => servers.iter().find(|server| &*server.name == name),
+        None if servers.len() == 1 => servers.first(),
+        None => return Err("Please specify which server to use.".into()),
+    };
+
+    server
+        .cloned()
+        .ok_or_else(|| format!("There is no server called {}.", name.unwrap_or_default()).into())
 }
 
 async fn get_uuid(mc_username: &str, mode: OfflineOnline) -> Result<PlayerUuid, Error> {
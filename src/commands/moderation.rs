@@ -0,0 +1,118 @@
+use std::fmt::Write;
+
+use super::{escape_code_block, truncate, MAX_MESSAGE_LENGTH};
+use crate::config::Tier;
+use crate::moderation::{self, strip_tags};
+use crate::{Context, Error};
+
+/// How many log entries are shown by `/moderation history`.
+const HISTORY_LIMIT: usize = 10;
+
+#[poise::command(slash_command, subcommands("rules", "history"))]
+pub async fn moderation(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, subcommands("list", "test"))]
+async fn rules(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// List the nickname moderation rules, in the order they are checked.
+#[poise::command(slash_command, guild_only, custom_data = "Tier::Moderator")]
+async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let settings = ctx.data().settings();
+    let rules = &settings.moderation.rules;
+
+    if rules.is_empty() {
+        ctx.say("There are no moderation rules.").await?;
+        return Ok(());
+    }
+
+    // the rules are split over as many code blocks (one per message) as needed
+    let max_length = MAX_MESSAGE_LENGTH - "```\n```".len();
+    let mut messages = vec![String::new()];
+    for (i, rule) in rules.iter().enumerate() {
+        let description = format!(
+            "{}. {}: /{}/\n   actions: {}\n   nickname: {}\n   reason: {}",
+            i + 1,
+            rule.name,
+            rule.pattern,
+            rule.describe_actions(),
+            rule.nickname,
+            rule.reason
+        );
+        let description = truncate(&escape_code_block(&description), max_length - 1);
+
+        let message = messages.last_mut().unwrap();
+        if !message.is_empty() && message.len() + description.len() + 1 > max_length {
+            messages.push(String::new());
+        }
+        writeln!(messages.last_mut().unwrap(), "{description}").unwrap();
+    }
+
+    for message in messages {
+        ctx.say(format!("```\n{message}```")).await?;
+    }
+
+    Ok(())
+}
+
+/// Check a nickname against the moderation rules.
+#[poise::command(slash_command, guild_only, custom_data = "Tier::Moderator")]
+async fn test(
+    ctx: Context<'_>,
+    #[description = "The nickname to check, with or without formatting tags."] text: String,
+) -> Result<(), Error> {
+    let settings = ctx.data().settings();
+    let nickname = strip_tags(&text);
+
+    let rule = settings.moderation.find(&nickname);
+    let nickname = escape_code_block(&nickname);
+    let reply = match rule {
+        Some(rule) => format!(
+            "```\n{nickname}\n```breaks the rule `{}` (actions: {}).",
+            rule.name,
+            rule.describe_actions()
+        ),
+        None => format!("```\n{nickname}\n```doesn't break any rules."),
+    };
+    ctx.say(reply).await?;
+
+    Ok(())
+}
+
+/// Show the automatic moderation actions taken against a player.
+#[poise::command(slash_command, guild_only, custom_data = "Tier::Moderator")]
+async fn history(
+    ctx: Context<'_>,
+    #[description = "The player's minecraft username."] player: String,
+) -> Result<(), Error> {
+    let entries = moderation::history(&ctx.data().store, player, HISTORY_LIMIT).await?;
+    if entries.is_empty() {
+        ctx.say("No actions were taken against that player.")
+            .await?;
+        return Ok(());
+    }
+
+    // newest first, leaving out whatever doesn't fit
+    let mut result = String::new();
+    for entry in &entries {
+        let description = moderation::describe(entry);
+        if result.len() + description.len() + 1 > MAX_MESSAGE_LENGTH {
+            break;
+        }
+        writeln!(&mut result, "{description}").unwrap();
+    }
+    if result.is_empty() {
+        // the nickname or the rule's name can make even a single entry too long
+        result = format!(
+            "The latest action is too long to show here; it was taken <t:{}:R>.",
+            entries[0].time
+        );
+    }
+
+    ctx.say(result).await?;
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use poise::serenity_prelude::CreateAttachment;
+use poise::{ChoiceParameter, CreateReply};
+
+use crate::config::Tier;
+use crate::perf;
+use crate::playtime::now;
+use crate::{Context, Error};
+
+#[derive(poise::ChoiceParameter, Copy, Clone, Debug, PartialEq, Eq)]
+enum Window {
+    #[name = "1h"]
+    Hour,
+    #[name = "24h"]
+    Day,
+    #[name = "7d"]
+    Week,
+}
+
+impl Window {
+    fn seconds(self) -> i64 {
+        match self {
+            Window::Hour => 60 * 60,
+            Window::Day => 60 * 60 * 24,
+            Window::Week => 60 * 60 * 24 * 7,
+        }
+    }
+}
+
+#[poise::command(slash_command, subcommands("graph"))]
+pub async fn perf(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show a graph of the server's TPS and player count.
+#[poise::command(slash_command, global_cooldown = 5, custom_data = "Tier::Member")]
+async fn graph(
+    ctx: Context<'_>,
+    #[description = "How far back the graph goes (1h by default)."] window: Option<Window>,
+    #[description = "The server to show."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    let window = window.unwrap_or(Window::Hour);
+    let end = now();
+    let start = end - window.seconds();
+
+    let samples = perf::samples(&ctx.data().store, server.name.to_string(), start).await?;
+    if samples.is_empty() {
+        ctx.say(format!(
+            "There is no data for {} in the last {}.",
+            server.name,
+            window.name()
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let png = tokio::task::spawn_blocking(move || perf::graph(&samples, start, end))
+        .await
+        .unwrap();
+
+    ctx.send(
+        CreateReply::default()
+            .content(format!("{} over the last {}:", server.name, window.name()))
+            .attachment(CreateAttachment::bytes(png, "perf.png")),
+    )
+    .await?;
+
+    Ok(())
+}
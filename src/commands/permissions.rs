@@ -0,0 +1,141 @@
+use poise::serenity_prelude::{ChannelId, CreateAllowedMentions, MessageBuilder, RoleId, UserId};
+use poise::CreateReply;
+
+use super::MAX_MESSAGE_LENGTH;
+use crate::config::Tier;
+use crate::{Context, Data, Error};
+
+type Command = poise::Command<Data, Error>;
+
+#[poise::command(slash_command, subcommands("show"))]
+pub async fn permissions(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// List who can use which command.
+#[poise::command(slash_command, guild_only, custom_data = "Tier::Member")]
+async fn show(
+    ctx: Context<'_>,
+    #[description = "Only show this command or group."] command: Option<String>,
+) -> Result<(), Error> {
+    let settings = ctx.data().settings();
+    let permissions = &settings.permissions;
+    let filter = command.map(|command| command.trim().trim_start_matches('/').to_owned());
+
+    let mut commands = vec![];
+    leaves(&ctx.framework().options().commands, &mut commands);
+    commands.retain(|command| {
+        filter.as_ref().is_none_or(|filter| {
+            command.qualified_name == *filter
+                || command.qualified_name.starts_with(&format!("{filter} "))
+        })
+    });
+    if commands.is_empty() {
+        ctx.say("There is no such command.").await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![];
+
+    let mut admins = vec![settings.op_role_id];
+    admins.extend(permissions.admin_role_ids.iter().map(|id| RoleId::new(*id)));
+    let tiers = [
+        ("Admins", admins),
+        ("Moderators", roles(&permissions.moderator_role_ids)),
+        ("Members", roles(&permissions.member_role_ids)),
+    ];
+    for (name, roles) in tiers {
+        let mut line = MessageBuilder::new();
+        line.push_bold(name).push(": ");
+        if roles.is_empty() {
+            line.push(if name == "Members" {
+                "everyone"
+            } else {
+                "nobody"
+            });
+        }
+        for (i, role) in roles.into_iter().enumerate() {
+            if i > 0 {
+                line.push(", ");
+            }
+            line.role(role);
+        }
+        lines.push(line.build());
+    }
+    lines.push(String::new());
+
+    for command in commands {
+        let default_tier = super::default_tier(command);
+        let config = permissions.command(&command.qualified_name);
+        let tier = config
+            .and_then(|config| config.tier)
+            .unwrap_or(default_tier);
+
+        let mut line = MessageBuilder::new();
+        line.push_mono_safe(format!("/{}", command.qualified_name))
+            .push(format!(": {}s", tier.name()));
+        if let Some(config) = config {
+            for role in &config.role_ids {
+                line.push(", ").role(RoleId::new(*role));
+            }
+            for user in &config.user_ids {
+                line.push(", ").user(UserId::new(*user));
+            }
+        }
+        if command.qualified_name == "run" {
+            for policy in &settings.run.policies {
+                line.push(", ").role(RoleId::new(policy.role_id));
+            }
+        }
+        if let Some(config) = config {
+            if !config.channel_ids.is_empty() {
+                line.push("; only in ");
+                for (i, channel) in config.channel_ids.iter().enumerate() {
+                    if i > 0 {
+                        line.push(", ");
+                    }
+                    line.channel(ChannelId::new(*channel));
+                }
+            }
+        }
+        lines.push(line.build());
+    }
+
+    // split the list into as many messages as needed
+    let mut messages = vec![String::new()];
+    for line in lines {
+        let message = messages.last_mut().unwrap();
+        if !message.is_empty() && message.len() + line.len() + 1 > MAX_MESSAGE_LENGTH {
+            messages.push(String::new());
+        }
+        let message = messages.last_mut().unwrap();
+        *message += &line;
+        *message += "\n";
+    }
+
+    for message in messages {
+        ctx.send(
+            CreateReply::default()
+                .content(message)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Collects the commands which can actually be used, i.e. the ones without subcommands.
+fn leaves<'a>(commands: &'a [Command], result: &mut Vec<&'a Command>) {
+    for command in commands {
+        if command.subcommands.is_empty() {
+            result.push(command);
+        } else {
+            leaves(&command.subcommands, result);
+        }
+    }
+}
+
+fn roles(ids: &[u64]) -> Vec<RoleId> {
+    ids.iter().map(|id| RoleId::new(*id)).collect()
+}
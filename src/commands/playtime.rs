@@ -0,0 +1,119 @@
+use std::fmt::Write;
+
+use crate::config::Tier;
+use crate::playtime::{self, format_duration};
+use crate::{Context, Error};
+
+/// How many players are shown by `/playtime top`.
+const TOP_LIMIT: usize = 15;
+
+#[derive(poise::ChoiceParameter, Copy, Clone, Debug, PartialEq, Eq)]
+enum Period {
+    #[name = "day"]
+    Day,
+    #[name = "week"]
+    Week,
+    #[name = "all"]
+    All,
+}
+
+impl Period {
+    /// Returns the unix time at which the period starts.
+    fn since(self) -> i64 {
+        match self {
+            Period::Day => playtime::now() - 60 * 60 * 24,
+            Period::Week => playtime::now() - 60 * 60 * 24 * 7,
+            Period::All => 0,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Period::Day => " in the last day",
+            Period::Week => " in the last week",
+            Period::All => "",
+        }
+    }
+}
+
+#[poise::command(slash_command, subcommands("player", "top"))]
+pub async fn playtime(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show how long a player has played.
+#[poise::command(slash_command, custom_data = "Tier::Member")]
+async fn player(
+    ctx: Context<'_>,
+    #[description = "The player's minecraft username."] name: String,
+    #[description = "The period to count playtime in (all time by default)."] period: Option<
+        Period,
+    >,
+    #[description = "Only count playtime on this server."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let period = period.unwrap_or(Period::All);
+    let store = &ctx.data().store;
+
+    let Some(playtime) =
+        playtime::player_playtime(store, name.clone(), server, period.since()).await?
+    else {
+        ctx.say("That player has never played.").await?;
+        return Ok(());
+    };
+
+    ctx.say(format!(
+        "```\n{} has played for {}{}.\n```",
+        playtime.name,
+        format_duration(playtime.seconds),
+        period.describe()
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Show the players who played the most.
+#[poise::command(slash_command, custom_data = "Tier::Member")]
+async fn top(
+    ctx: Context<'_>,
+    #[description = "The period to count playtime in (all time by default)."] period: Option<
+        Period,
+    >,
+    #[description = "Only count playtime on this server."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let period = period.unwrap_or(Period::All);
+    let store = &ctx.data().store;
+
+    let top = playtime::top(store, server, period.since(), TOP_LIMIT).await?;
+    if top.is_empty() {
+        ctx.say(format!("Nobody has played{}.", period.describe()))
+            .await?;
+        return Ok(());
+    }
+
+    let width = top
+        .iter()
+        .map(|playtime| playtime.name.len())
+        .max()
+        .unwrap();
+    let mut result = format!("Top players{}:\n```\n", period.describe());
+    for (i, playtime) in top.iter().enumerate() {
+        writeln!(
+            &mut result,
+            "{:>2}. {:<width$}  {}",
+            i + 1,
+            playtime.name,
+            format_duration(playtime.seconds)
+        )
+        .unwrap();
+    }
+    result += "```";
+
+    ctx.say(result).await?;
+
+    Ok(())
+}
@@ -1,40 +1,180 @@
+use std::time::Duration;
+
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateAttachment, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, MessageBuilder,
+};
+use poise::CreateReply;
+
+use super::{escape_code_block, MAX_MESSAGE_LENGTH};
+use crate::audit::{self, AuditEntry};
+use crate::config::Tier;
+use crate::formatting::Text;
+use crate::interface;
+use crate::playtime::now;
+use crate::run_policy::Denial;
 use crate::{Context, Error};
 
-const MAXLEN: usize = 2000 - "Success:\n```\n\n```".len();
-const CUTOFF_SUFFIX: &str = "...";
+const SUCCESS: &str = "Success:\n";
+
+/// How long the invoker has to confirm a dangerous command.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Run an admin command from the console.
-#[poise::command(slash_command, guild_only, check = "super::operator_only")]
+// this is for admins, and for the roles of the run policies, which decide what they may run
+#[poise::command(slash_command, guild_only)]
 pub async fn run(
     ctx: Context<'_>,
     #[description = "The command to run"] cmd: String,
+    #[description = "The server to run the command on."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
 ) -> Result<(), Error> {
-    let interface = &mut *ctx.data().interface.lock().await;
+    let server = super::get_server(ctx, server.as_deref())?;
+    let settings = ctx.data().settings();
+
+    let roles = match ctx.author_member().await {
+        Some(member) => member.roles.clone(),
+        None => vec![],
+    };
+    let is_op = settings.permissions.tier(settings.op_role_id, &roles) == Some(Tier::Admin);
+    if let Err(denial) = settings.run.check(&cmd, is_op, &roles) {
+        let mut reply = MessageBuilder::new();
+        match denial {
+            Denial::Denied { policy, pattern } => reply
+                .push("This command is denied by the ")
+                .push_mono_safe(&policy.name)
+                .push(" policy (")
+                .push_mono_safe(pattern.to_string())
+                .push(")."),
+            Denial::NotAllowed => reply.push("None of your roles are allowed to run this command."),
+        };
+        let reply = reply.build();
+        record(ctx, &server.name, cmd, format!("denied: {reply}")).await;
+        ctx.say(reply).await?;
+        return Ok(());
+    }
+
+    if settings
+        .run
+        .confirm
+        .iter()
+        .any(|pattern| pattern.matches_nested(&cmd))
+        && !confirm(ctx, &cmd).await?
+    {
+        return Ok(());
+    }
+
+    let result = server.interface.lock().await.exec(&cmd).await;
+
+    let response = match result {
+        Ok(response) => Ok(Text::parse(&response)),
+        Err(interface::Error::Auth) => Err("Invalid authentication (check bot config)."),
+        Err(interface::Error::CommandTooLong) => Err("Command too long."),
+        Err(interface::Error::Io(..)) => Err("The server is closed."),
+    };
 
-    match interface.exec(&cmd).await {
+    match response {
         Ok(response) => {
-            let response = if response.chars().count() > MAXLEN {
-                response
-                    .chars()
-                    .take(MAXLEN - CUTOFF_SUFFIX.len())
-                    .chain(CUTOFF_SUFFIX.chars())
-                    .collect()
+            let plain = response.plain();
+            let block = if response.is_styled() {
+                format!("```ansi\n{}\n```", response.ansi())
             } else {
-                response
+                format!("```\n{}\n```", escape_code_block(&plain))
             };
-            ctx.say(format!("Success:\n```\n{response}\n```")).await?;
-        }
-        Err(rcon::Error::Auth) => {
-            ctx.say("Invalid authentication (check bot config).")
-                .await?;
-        }
-        Err(rcon::Error::CommandTooLong) => {
-            ctx.say("Command too long.").await?;
+
+            // the whole response is attached instead of cutting it off
+            let reply = if SUCCESS.len() + block.len() > MAX_MESSAGE_LENGTH {
+                CreateReply::default()
+                    .content("Success (the response is attached):")
+                    .attachment(CreateAttachment::bytes(plain.as_bytes(), "response.txt"))
+            } else {
+                CreateReply::default().content(format!("{SUCCESS}{block}"))
+            };
+            record(ctx, &server.name, cmd, plain).await;
+            ctx.send(reply).await?;
         }
-        Err(rcon::Error::Io(..)) => {
-            ctx.say("The server is closed.").await?;
+        Err(reply) => {
+            record(ctx, &server.name, cmd, format!("error: {reply}")).await;
+            ctx.say(reply).await?;
         }
     }
 
     Ok(())
 }
+
+/// Asks the invoker (and nobody else) to confirm a command using buttons. Returns whether they did
+/// within [`CONFIRM_TIMEOUT`].
+async fn confirm(ctx: Context<'_>, cmd: &str) -> Result<bool, Error> {
+    let confirm_id = format!("{}-confirm", ctx.id());
+    let cancel_id = format!("{}-cancel", ctx.id());
+
+    let prompt = MessageBuilder::new()
+        .push("Are you sure you want to run ")
+        .push_mono_safe(cmd)
+        .push("?")
+        .build();
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&confirm_id)
+            .label("Confirm")
+            .style(ButtonStyle::Danger),
+        CreateButton::new(&cancel_id)
+            .label("Cancel")
+            .style(ButtonStyle::Secondary),
+    ]);
+    let handle = ctx
+        .send(
+            CreateReply::default()
+                .content(prompt)
+                .components(vec![buttons])
+                .ephemeral(true),
+        )
+        .await?;
+
+    let ids = (confirm_id.clone(), cancel_id);
+    let press = ComponentInteractionCollector::new(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(CONFIRM_TIMEOUT)
+        .filter(move |press| press.data.custom_id == ids.0 || press.data.custom_id == ids.1)
+        .await;
+
+    let Some(press) = press else {
+        let reply = CreateReply::default()
+            .content("The command wasn't confirmed in time, so it wasn't run.")
+            .components(vec![]);
+        handle.edit(ctx, reply).await?;
+        return Ok(false);
+    };
+
+    let confirmed = press.data.custom_id == confirm_id;
+    let content = match confirmed {
+        true => "Running the command...",
+        false => "The command was cancelled.",
+    };
+    let response = CreateInteractionResponseMessage::new()
+        .content(content)
+        .components(vec![]);
+    press
+        .create_response(ctx, CreateInteractionResponse::UpdateMessage(response))
+        .await?;
+
+    Ok(confirmed)
+}
+
+/// Records a command in the audit log.
+async fn record(ctx: Context<'_>, server: &str, command: String, response: String) {
+    let entry = AuditEntry {
+        time: now(),
+        user_id: ctx.author().id.get(),
+        user_name: ctx.author().name.clone(),
+        guild_id: ctx.guild_id().map(|id| id.get()),
+        server: server.to_owned(),
+        command,
+        response,
+    };
+    let channel_id = ctx.data().settings().audit_channel_id;
+    if let Err(why) = audit::record(&ctx.data().store, ctx.http(), channel_id, entry).await {
+        log::error!("Couldn't record /run in the audit log: {why}");
+    }
+}
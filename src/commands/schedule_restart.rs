@@ -1,25 +1,153 @@
+use std::fmt::Write;
+
+use crate::audit::parse_duration;
+use crate::config::Tier;
+use crate::restarts::{self, ScheduledRestart};
+use crate::server::Server;
 use crate::{Context, Error};
 
+#[poise::command(
+    slash_command,
+    subcommands("when_empty", "at", "in_", "cancel", "status")
+)]
+pub async fn schedule_restart(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
 /// Schedule a server restart as soon as everyone logs off.
-#[poise::command(slash_command, guild_only, check = "super::operator_only")]
-pub async fn schedule_restart(
+#[poise::command(slash_command, guild_only)]
+async fn when_empty(
     ctx: Context<'_>,
-    #[description = "Whether or not to cancel an already scheduled restart."] cancel: Option<bool>,
+    #[description = "The server to restart."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
 ) -> Result<(), Error> {
-    let cancel = cancel.unwrap_or(false);
-    let restart_scheduled = &mut *ctx.data().restart_scheduled.lock().await;
-
-    if cancel && *restart_scheduled {
-        *restart_scheduled = false;
-        ctx.say("The scheduled restart has been cancelled.").await?;
-    } else if cancel {
-        ctx.say("There is no restart scheduled.").await?;
-    } else if *restart_scheduled {
-        ctx.say("There is already a restart scheduled.").await?;
-    } else {
-        *restart_scheduled = true;
+    let server = super::get_server(ctx, server.as_deref())?;
+    if schedule(ctx, &server, ScheduledRestart::WhenEmpty).await? {
         ctx.say("A restart has been scheduled - it will occur as soon as everyone logs off.")
             .await?;
     }
     Ok(())
 }
+
+/// Schedule a server restart at a given time, with warnings in the game beforehand.
+#[poise::command(slash_command, guild_only)]
+async fn at(
+    ctx: Context<'_>,
+    #[description = "The time in UTC, either HH:MM or YYYY-MM-DD HH:MM."] time: String,
+    #[description = "The server to restart."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    let now = restarts::unix_time();
+    let time = match restarts::parse_time(&time, now) {
+        Some(time) if time > now => time,
+        Some(_) => return Err("That time has already passed.".into()),
+        None => return Err("The time should be HH:MM or YYYY-MM-DD HH:MM (in UTC).".into()),
+    };
+
+    if schedule(ctx, &server, ScheduledRestart::At(time)).await? {
+        ctx.say(format!(
+            "A restart has been scheduled <t:{time}:R>, at <t:{time}:f>."
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// Schedule a server restart after a while, with warnings in the game beforehand.
+#[poise::command(slash_command, guild_only, rename = "in")]
+async fn in_(
+    ctx: Context<'_>,
+    #[description = "How long until the restart, e.g. 30m or 2h."] duration: String,
+    #[description = "The server to restart."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    let Some(duration) = parse_duration(&duration).filter(|duration| *duration > 0) else {
+        return Err("The duration should be something like 30m or 2h.".into());
+    };
+    let time = restarts::unix_time() + duration;
+
+    if schedule(ctx, &server, ScheduledRestart::At(time)).await? {
+        ctx.say(format!(
+            "A restart has been scheduled <t:{time}:R>, at <t:{time}:f>."
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// Cancel the scheduled restart. Daily restarts from the config aren't affected.
+#[poise::command(slash_command, guild_only)]
+async fn cancel(
+    ctx: Context<'_>,
+    #[description = "The server whose restart to cancel."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    if server.scheduled_restart.lock().await.is_none() {
+        ctx.say("There is no restart scheduled.").await?;
+        return Ok(());
+    }
+
+    restarts::set(&ctx.data().store, &server, None).await?;
+    ctx.say("The scheduled restart has been cancelled.").await?;
+    Ok(())
+}
+
+/// Show the pending restarts.
+#[poise::command(slash_command, guild_only, custom_data = "Tier::Moderator")]
+async fn status(
+    ctx: Context<'_>,
+    #[description = "The server whose restarts to show."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+
+    let mut result = match *server.scheduled_restart.lock().await {
+        None => "There is no restart scheduled.".to_owned(),
+        Some(ScheduledRestart::WhenEmpty) => {
+            "A restart is scheduled for as soon as everyone logs off.".to_owned()
+        }
+        Some(ScheduledRestart::At(time)) => {
+            format!("A restart is scheduled <t:{time}:R>, at <t:{time}:f>.")
+        }
+    };
+
+    let daily = &server.daily_restarts;
+    if let Some(next) = restarts::next_daily(daily, restarts::unix_time()) {
+        let times = daily
+            .iter()
+            .map(|time| format!("{:02}:{:02}", time.0 / 3600, time.0 / 60 % 60))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            result,
+            "\nThe server restarts daily at {times} UTC, next <t:{next}:R>."
+        )
+        .unwrap();
+    }
+
+    ctx.say(result).await?;
+    Ok(())
+}
+
+/// Schedules `restart` unless there's a restart scheduled already, returning whether it was.
+async fn schedule(
+    ctx: Context<'_>,
+    server: &Server,
+    restart: ScheduledRestart,
+) -> Result<bool, Error> {
+    if server.scheduled_restart.lock().await.is_some() {
+        ctx.say("There is already a restart scheduled.").await?;
+        return Ok(false);
+    }
+
+    restarts::set(&ctx.data().store, server, Some(restart)).await?;
+    Ok(true)
+}
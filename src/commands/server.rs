@@ -0,0 +1,176 @@
+use std::time::UNIX_EPOCH;
+
+use super::{escape_code_block, truncate, MAX_MESSAGE_LENGTH};
+use crate::config::Tier;
+use crate::supervisor::{Action, State, Status};
+use crate::{Context, Error};
+
+/// How many lines of the server's output are shown by `/server status`.
+const OUTPUT_LINES: usize = 10;
+
+#[poise::command(
+    slash_command,
+    subcommands("start", "stop", "restart", "kill", "status")
+)]
+pub async fn server(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Start the server.
+#[poise::command(slash_command, guild_only)]
+async fn start(
+    ctx: Context<'_>,
+    #[description = "The server to start."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    request(ctx, server, Action::Start, "The server is starting.").await
+}
+
+/// Stop the server, without restarting it.
+#[poise::command(slash_command, guild_only)]
+async fn stop(
+    ctx: Context<'_>,
+    #[description = "The server to stop."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    request(ctx, server, Action::Stop, "The server is stopping.").await
+}
+
+/// Stop the server and start it again.
+#[poise::command(slash_command, guild_only)]
+async fn restart(
+    ctx: Context<'_>,
+    #[description = "The server to restart."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    request(ctx, server, Action::Restart, "The server is restarting.").await
+}
+
+/// Kill the server's process, without letting it save.
+#[poise::command(slash_command, guild_only)]
+async fn kill(
+    ctx: Context<'_>,
+    #[description = "The server to kill."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    request(ctx, server, Action::Kill, "The server has been killed.").await
+}
+
+/// Show whether the server is running, and its latest output.
+#[poise::command(slash_command, guild_only, custom_data = "Tier::Moderator")]
+async fn status(
+    ctx: Context<'_>,
+    #[description = "The server whose status to show."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    let Some(supervisor) = &server.supervisor else {
+        ctx.say(format!("{} isn't run by the bot.", server.name))
+            .await?;
+        return Ok(());
+    };
+
+    let status = supervisor.request(Action::Status).await?;
+    ctx.say(format_status(&status)).await?;
+    Ok(())
+}
+
+async fn request(
+    ctx: Context<'_>,
+    server: Option<String>,
+    action: Action,
+    success: &str,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    let Some(supervisor) = &server.supervisor else {
+        ctx.say(format!("{} isn't run by the bot.", server.name))
+            .await?;
+        return Ok(());
+    };
+
+    match supervisor.request(action).await {
+        Ok(_) => ctx.say(success).await?,
+        Err(why) => ctx.say(why).await?,
+    };
+    Ok(())
+}
+
+fn format_status(status: &Status) -> String {
+    let timestamp = |time: std::time::SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    };
+
+    let mut result = match &status.state {
+        State::Stopped => "The server is stopped.".to_owned(),
+        State::Running {
+            pid,
+            since,
+            stopping,
+        } => format!(
+            "The server is {} (pid {}) since <t:{}:R>.",
+            if *stopping { "stopping" } else { "running" },
+            pid.map_or_else(|| "unknown".to_owned(), |pid| pid.to_string()),
+            timestamp(*since)
+        ),
+        State::Restarting { at } => {
+            format!("The server will be restarted <t:{}:R>.", timestamp(*at))
+        }
+    };
+
+    result += &format!("\nAutomatic restarts: {}", status.restarts);
+    if let Some(exit) = status.last_exit {
+        result += &format!("\nLast exit: {exit}");
+    }
+
+    // keep within the message length limit, dropping the oldest lines first. a single line which
+    // is too long on its own is cut short, so the latest one is always shown
+    let max_length = MAX_MESSAGE_LENGTH - result.len() - "\n```\n\n```".len();
+    let start = status.output.len().saturating_sub(OUTPUT_LINES);
+    let mut output = status.output[start..]
+        .iter()
+        .map(|line| truncate(&escape_code_block(line), max_length))
+        .collect::<Vec<_>>()
+        .join("\n");
+    while output.len() > max_length {
+        let cut = output.find('\n').map_or(output.len(), |i| i + 1);
+        output.drain(..cut);
+    }
+    if !output.is_empty() {
+        result += &format!("\n```\n{output}\n```");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(output: &[String]) -> Status {
+        Status {
+            state: State::Stopped,
+            restarts: 0,
+            last_exit: None,
+            output: output.to_vec(),
+        }
+    }
+
+    #[test]
+    fn long_output() {
+        let lines = vec!["x".repeat(300); 20];
+        let message = format_status(&status(&lines));
+        assert!(message.len() <= MAX_MESSAGE_LENGTH);
+        assert_eq!(message.matches(&lines[0]).count(), 6);
+
+        // a line which doesn't fit on its own is cut short instead of being left out
+        let message = format_status(&status(&["y".repeat(5000)]));
+        assert!(message.len() <= MAX_MESSAGE_LENGTH);
+        assert!(message.contains(&format!("{}...\n```", "y".repeat(100))));
+    }
+}
@@ -1,7 +1,8 @@
+use crate::config::Tier;
 use crate::{Context, Error};
 
 /// Get a link to the bot's source code.
-#[poise::command(slash_command)]
+#[poise::command(slash_command, custom_data = "Tier::Member")]
 pub async fn source(ctx: Context<'_>) -> Result<(), Error> {
     ctx.say("<https://github.com/booleancoercion/ferrisquery>")
         .await?;
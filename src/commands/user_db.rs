@@ -3,7 +3,7 @@ use poise::{serenity_prelude::CreateAllowedMentions, CreateReply};
 use crate::{database_api, Context, Error};
 
 pub async fn db_available(ctx: Context<'_>) -> Result<bool, Error> {
-    Ok(ctx.data().db_api.is_some())
+    Ok(ctx.data().settings().db_api.is_some())
 }
 
 /// Manipulate the user database directly. This usually isn't necessary.
@@ -19,13 +19,13 @@ async fn fetch(_: Context<'_>) -> Result<(), Error> {
 }
 
 /// Fetch a profile using a minecraft username.
-#[poise::command(slash_command, check = "super::operator_only", check = "db_available")]
+#[poise::command(slash_command, check = "db_available")]
 async fn with_mc(
     ctx: Context<'_>,
     #[description = "The minecraft username."] mc_name: String,
     #[description = "Whether it's an online user or an offline one."] mode: super::OfflineOnline,
 ) -> Result<(), Error> {
-    let db_api = ctx.data().db_api.as_ref().unwrap();
+    let db_api = ctx.data().settings().db_api.clone().unwrap();
 
     let uuid = super::get_uuid(&mc_name, mode).await?;
     let user = match db_api.get_users_with_minecraft(uuid).await {
@@ -50,12 +50,12 @@ async fn with_mc(
 }
 
 /// Fetch a profile using a discord user.
-#[poise::command(slash_command, check = "super::operator_only", check = "db_available")]
+#[poise::command(slash_command, check = "db_available")]
 async fn with_discord(
     ctx: Context<'_>,
     #[description = "The discord user."] user: poise::serenity_prelude::User,
 ) -> Result<(), Error> {
-    let db_api = ctx.data().db_api.as_ref().unwrap();
+    let db_api = ctx.data().settings().db_api.clone().unwrap();
 
     let user = match db_api.get_users_with_discord(user.id).await {
         Ok(user) => user,
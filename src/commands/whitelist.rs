@@ -7,6 +7,8 @@ use serde_json::Value;
 use tokio::io::AsyncWriteExt;
 use uuid_mc::{PlayerUuid, Uuid};
 
+use crate::config::Tier;
+use crate::server::Server;
 use crate::{Context, Error};
 
 #[derive(Serialize, Deserialize)]
@@ -15,7 +17,7 @@ struct WhitelistEntry<'a> {
     uuid: PlayerUuid,
 }
 
-async fn get_whitelist(server_directory: &str) -> Result<Vec<WhitelistEntry>, Error> {
+async fn get_whitelist(server_directory: &str) -> Result<Vec<WhitelistEntry<'_>>, Error> {
     let filename = format!("{server_directory}/whitelist.json");
     let raw_json = tokio::fs::read_to_string(&filename).await?;
     let mut whitelist: Vec<WhitelistEntry> = serde_json::from_str(&raw_json)?;
@@ -25,8 +27,8 @@ async fn get_whitelist(server_directory: &str) -> Result<Vec<WhitelistEntry>, Er
     Ok(whitelist)
 }
 
-async fn save_whitelist(ctx: &Context<'_>, whitelist: &[WhitelistEntry<'_>]) -> Result<(), Error> {
-    let filename = format!("{}/whitelist.json", ctx.data().server_directory);
+async fn save_whitelist(server: &Server, whitelist: &[WhitelistEntry<'_>]) -> Result<(), Error> {
+    let filename = format!("{}/whitelist.json", server.directory);
 
     let raw_json = serde_json::to_string_pretty(whitelist).unwrap(); // this serialization cannot fail
     let mut file = tokio::fs::OpenOptions::new()
@@ -38,7 +40,7 @@ async fn save_whitelist(ctx: &Context<'_>, whitelist: &[WhitelistEntry<'_>]) ->
 
     // we don't care if the command succeeds, because then that means the server
     // is offline and so the whitelist will be reloaded anyway when it comes online.
-    let mut interface = ctx.data().interface.lock().await;
+    let mut interface = server.interface.lock().await;
     let _ = interface.exec("whitelist reload").await;
 
     Ok(())
@@ -51,8 +53,8 @@ async fn get_easyauth_config(server_directory: &str) -> Result<Value, Error> {
     Ok(serde_json::from_str(&raw_json)?)
 }
 
-async fn save_easyauth_config(ctx: &Context<'_>, config: &Value) -> Result<(), Error> {
-    let filename = format!("{}/mods/EasyAuth/config.json", ctx.data().server_directory);
+async fn save_easyauth_config(server: &Server, config: &Value) -> Result<(), Error> {
+    let filename = format!("{}/mods/EasyAuth/config.json", server.directory);
 
     let raw_json = serde_json::to_string_pretty(config).unwrap(); // this serialization cannot fail
     let mut file = tokio::fs::OpenOptions::new()
@@ -64,7 +66,7 @@ async fn save_easyauth_config(ctx: &Context<'_>, config: &Value) -> Result<(), E
 
     // we don't care if the command succeeds, because then that means the server
     // is offline and so the whitelist will be reloaded anyway when it comes online.
-    let mut interface = ctx.data().interface.lock().await;
+    let mut interface = server.interface.lock().await;
     let _ = interface.exec("auth reload").await;
 
     Ok(())
@@ -76,17 +78,21 @@ pub async fn whitelist(_: Context<'_>) -> Result<(), Error> {
 }
 
 /// Add a user to the whitelist.
-#[poise::command(slash_command, guild_only, check = "super::operator_only")]
+#[poise::command(slash_command, guild_only)]
 async fn add(
     ctx: Context<'_>,
     #[description = "The minecraft user to be added."] username: String,
     #[description = "The associated discord user."] discord: poise::serenity_prelude::User,
     #[description = "Whether the user uses online or offline mode."] mode: super::OfflineOnline,
+    #[description = "The server whose whitelist to modify."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
 ) -> Result<(), Error> {
-    let db_api = ctx.data().db_api.as_ref();
+    let server = super::get_server(ctx, server.as_deref())?;
+    let db_api = ctx.data().settings().db_api.clone();
 
     // Adding to the whitelist file
-    let mut whitelist = get_whitelist(&ctx.data().server_directory).await?;
+    let mut whitelist = get_whitelist(&server.directory).await?;
     if whitelist.iter().any(|entry| entry.name == username) {
         ctx.say(format!("The user {username} is already in the whitelist."))
             .await?;
@@ -99,11 +105,11 @@ async fn add(
         name: username.as_str().into(),
         uuid,
     });
-    save_whitelist(&ctx, &whitelist).await?;
+    save_whitelist(&server, &whitelist).await?;
 
     // Modifying the EasyAuth config, if necessary
-    if ctx.data().has_easyauth && mode == super::OfflineOnline::Offline {
-        let mut config = get_easyauth_config(&ctx.data().server_directory).await?;
+    if server.has_easyauth && mode == super::OfflineOnline::Offline {
+        let mut config = get_easyauth_config(&server.directory).await?;
         let Some(forced_offline_players) = config
             .get_mut("main")
             .and_then(|x| x.get_mut("forcedOfflinePlayers"))
@@ -121,7 +127,7 @@ async fn add(
             forced_offline_players.push(Value::String(lowercase_username))
         }
 
-        save_easyauth_config(&ctx, &config).await?;
+        save_easyauth_config(&server, &config).await?;
     }
 
     // Saving to monad's database
@@ -151,14 +157,16 @@ async fn remove(_: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-async fn remove_mc_inner<'a>(
+async fn remove_mc_inner(
     ctx: Context<'_>,
+    server: Option<String>,
     condition: impl Fn(&WhitelistEntry) -> bool,
 ) -> Result<(), Error> {
-    let db_api = ctx.data().db_api.as_ref();
+    let server = super::get_server(ctx, server.as_deref())?;
+    let db_api = ctx.data().settings().db_api.clone();
 
     // Removing from the whitelist file
-    let mut whitelist = get_whitelist(&ctx.data().server_directory).await?;
+    let mut whitelist = get_whitelist(&server.directory).await?;
     let entry = whitelist.iter().find(|entry| condition(entry));
     let Some(entry) = entry else {
         ctx.say("That user is not in the whitelist.").await?;
@@ -170,11 +178,11 @@ async fn remove_mc_inner<'a>(
 
     whitelist.retain(|entry| entry.name != username);
 
-    save_whitelist(&ctx, &whitelist).await?;
+    save_whitelist(&server, &whitelist).await?;
 
     // Removign from the EasyAuth config, if necessary
-    if ctx.data().has_easyauth && uuid.offline().is_some() {
-        let mut config = get_easyauth_config(&ctx.data().server_directory).await?;
+    if server.has_easyauth && uuid.offline().is_some() {
+        let mut config = get_easyauth_config(&server.directory).await?;
         let Some(forced_offline_players) = config
             .get_mut("main")
             .and_then(|x| x.get_mut("forcedOfflinePlayers"))
@@ -186,7 +194,7 @@ async fn remove_mc_inner<'a>(
         let lowercase_username = username.to_lowercase();
         forced_offline_players.retain(|v| v.as_str().unwrap() != lowercase_username);
 
-        save_easyauth_config(&ctx, &config).await?;
+        save_easyauth_config(&server, &config).await?;
     }
 
     // Removing from monad's database
@@ -205,34 +213,42 @@ async fn remove_mc_inner<'a>(
 }
 
 /// Remove a user from the whitelist using their minecraft username.
-#[poise::command(
-    slash_command,
-    guild_only,
-    check = "super::operator_only",
-    rename = "with_mc_username"
-)]
-async fn remove_with_mc_username(ctx: Context<'_>, username: String) -> Result<(), Error> {
-    remove_mc_inner(ctx, |entry| entry.name == username).await
+#[poise::command(slash_command, guild_only, rename = "with_mc_username")]
+async fn remove_with_mc_username(
+    ctx: Context<'_>,
+    username: String,
+    #[description = "The server whose whitelist to modify."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    remove_mc_inner(ctx, server, |entry| entry.name == username).await
 }
 
-#[poise::command(
-    slash_command,
-    guild_only,
-    check = "super::operator_only",
-    rename = "with_mc_uuid"
-)]
-async fn remove_with_mc_uuid(ctx: Context<'_>, uuid: String) -> Result<(), Error> {
+#[poise::command(slash_command, guild_only, rename = "with_mc_uuid")]
+async fn remove_with_mc_uuid(
+    ctx: Context<'_>,
+    uuid: String,
+    #[description = "The server whose whitelist to modify."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
     let Ok(Ok(uuid)) = Uuid::try_parse(&uuid).map(PlayerUuid::new_with_uuid) else {
         ctx.say("The provided UUID is invalid.").await?;
         return Ok(());
     };
-    remove_mc_inner(ctx, |entry| entry.uuid == uuid).await
+    remove_mc_inner(ctx, server, |entry| entry.uuid == uuid).await
 }
 
 /// Return the list of whitelisted players.
-#[poise::command(slash_command, guild_only)]
-async fn list(ctx: Context<'_>) -> Result<(), Error> {
-    let whitelist = get_whitelist(&ctx.data().server_directory).await?;
+#[poise::command(slash_command, guild_only, custom_data = "Tier::Moderator")]
+async fn list(
+    ctx: Context<'_>,
+    #[description = "The server whose whitelist to show."]
+    #[autocomplete = "super::autocomplete_server"]
+    server: Option<String>,
+) -> Result<(), Error> {
+    let server = super::get_server(ctx, server.as_deref())?;
+    let whitelist = get_whitelist(&server.directory).await?;
 
     let mut result = format!("There are {} whitelisted players", whitelist.len());
     if whitelist.is_empty() {
@@ -255,3 +271,52 @@ async fn list(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rcon::MockRcon;
+
+    #[tokio::test]
+    async fn saving_reloads_the_whitelist() {
+        let directory =
+            std::env::temp_dir().join(format!("ferrisquery-whitelist-{}", std::process::id()));
+        let directory = directory.to_str().unwrap();
+        tokio::fs::create_dir_all(directory).await.unwrap();
+        let filename = format!("{directory}/whitelist.json");
+        tokio::fs::write(
+            &filename,
+            r#"[
+                {"name": "jeb_", "uuid": "853c80ef-3c37-49fd-aa49-938b674adae6"},
+                {"name": "Notch", "uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5"}
+            ]"#,
+        )
+        .await
+        .unwrap();
+
+        let rcon = MockRcon::start("hunter2").await;
+        rcon.respond("whitelist reload", "Reloaded the whitelist");
+        let server = rcon.server(false, directory);
+
+        let mut whitelist = get_whitelist(directory).await.unwrap();
+        let names: Vec<_> = whitelist.iter().map(|entry| &*entry.name).collect();
+        assert_eq!(names, ["Notch", "jeb_"]);
+
+        let uuid = Uuid::try_parse("61699b2e-d327-4a01-9f1e-0ea8c3f06bc6").unwrap();
+        whitelist.push(WhitelistEntry {
+            name: "Dinnerbone".into(),
+            uuid: PlayerUuid::new_with_uuid(uuid).unwrap(),
+        });
+        save_whitelist(&server, &whitelist).await.unwrap();
+        assert_eq!(rcon.commands(), ["whitelist reload"]);
+        assert_eq!(get_whitelist(directory).await.unwrap().len(), 3);
+
+        // the server reloads the whitelist by itself when it starts
+        drop(rcon);
+        whitelist.pop();
+        save_whitelist(&server, &whitelist).await.unwrap();
+        assert_eq!(get_whitelist(directory).await.unwrap().len(), 2);
+
+        tokio::fs::remove_dir_all(directory).await.unwrap();
+    }
+}
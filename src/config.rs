@@ -0,0 +1,1616 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use toml::value::Table;
+use toml::Value;
+
+/// How to use the command line, which is shown for `--help` and when it's used wrong.
+pub const USAGE: &str = "usage: ferrisquery [<config path> | --config <config path>]";
+
+/// The path used when none is given on the command line.
+const DEFAULT_CONFIG_PATH: &str = "ferrisquery.toml";
+
+/// The name given to the server that is configured using the unprefixed per-server environment
+/// variables, if the config file doesn't define any servers.
+const DEFAULT_SERVER_NAME: &str = "main";
+
+static EMPTY_TABLE: Lazy<Table> = Lazy::new(Table::new);
+
+/// Environment variables which override the global config keys.
+const GLOBAL_ENV_OVERRIDES: &[(&str, &[&str])] = &[
+    ("DISCORD_TOKEN", &["discord", "token"]),
+    ("OP_ROLE_ID", &["discord", "op_role_id"]),
+    ("DB_USERNAME", &["database", "username"]),
+    ("DB_ADMIN_ENDPOINT", &["database", "admin_endpoint"]),
+    ("DB_ADMIN_PASSWORD", &["database", "admin_password"]),
+    ("DB_USER_ENDPOINT", &["database", "user_endpoint"]),
+    ("DB_USER_PASSWORD", &["database", "user_password"]),
+    ("STORAGE_PATH", &["storage", "path"]),
+    ("LIST_STYLE", &["list", "style"]),
+    ("LIST_HEAD_URL", &["list", "head_url"]),
+    ("LIST_LOW_TPS", &["list", "low_tps"]),
+    ("ALERTS_CHANNEL_ID", &["alerts", "channel_id"]),
+    ("MODERATION_CHANNEL_ID", &["moderation", "channel_id"]),
+    ("AUDIT_CHANNEL_ID", &["audit", "channel_id"]),
+    ("RUN_CONFIRM", &["run", "confirm"]),
+    ("ADMIN_ROLE_IDS", &["permissions", "admin_role_ids"]),
+    ("MODERATOR_ROLE_IDS", &["permissions", "moderator_role_ids"]),
+    ("MEMBER_ROLE_IDS", &["permissions", "member_role_ids"]),
+    ("ALERTS_COOLDOWN", &["alerts", "cooldown"]),
+];
+
+/// Environment variables which override the config keys of a server. These are prefixed with the
+/// server's name in uppercase (e.g. `SURVIVAL_RCON_ADDR`), or unprefixed if there's only one server.
+const SERVER_ENV_OVERRIDES: &[(&str, &[&str])] = &[
+    ("RCON_ADDR", &["rcon", "address"]),
+    ("RCON_PASS", &["rcon", "password"]),
+    ("LIST_CHANNEL_ID", &["list_channel_id"]),
+    ("EVENTS_CHANNEL_ID", &["events_channel_id"]),
+    ("CHAT_CHANNEL_ID", &["chat_channel_id"]),
+    ("DAILY_RESTARTS", &["daily_restarts"]),
+    ("ANNOUNCEMENTS_CHANNEL_ID", &["announcements", "channel_id"]),
+    ("SERVER_COMMAND", &["supervisor", "command"]),
+    ("SERVER_DIR", &["directory"]),
+    ("HAS_LIST_JSON", &["features", "list_json"]),
+    ("HAS_EASYAUTH", &["features", "easyauth"]),
+    ("STATUS_SOURCES", &["status", "sources"]),
+    ("SLP_ADDR", &["status", "slp_address"]),
+    ("QUERY_ADDR", &["status", "query_address"]),
+];
+
+pub struct Config {
+    pub discord: DiscordConfig,
+    pub database: Option<DatabaseConfig>,
+    pub moderation: ModerationConfig,
+    pub storage: StorageConfig,
+    pub list: ListConfig,
+    pub alerts: Option<AlertsConfig>,
+    pub audit: AuditConfig,
+    pub run: RunConfig,
+    pub permissions: PermissionsConfig,
+    pub servers: Vec<ServerConfig>,
+}
+
+pub struct DiscordConfig {
+    pub token: String,
+    pub op_role_id: u64,
+}
+
+#[derive(PartialEq, Eq)]
+pub struct DatabaseConfig {
+    pub username: String,
+    pub admin_endpoint: String,
+    pub admin_password: String,
+    pub user_endpoint: String,
+    pub user_password: String,
+}
+
+pub struct StorageConfig {
+    /// The path of the SQLite database holding the bot's persistent data (e.g. playtime).
+    pub path: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            path: "ferrisquery.sqlite3".into(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct ListConfig {
+    pub style: ListStyle,
+    /// The URL of a player's head, with `{uuid}` and `{name}` standing in for the player's UUID and
+    /// name. If unset, no heads are shown.
+    pub head_url: Option<String>,
+    /// The 1m TPS below which the server is shown as lagging.
+    pub low_tps: f32,
+}
+
+impl Default for ListConfig {
+    fn default() -> Self {
+        Self {
+            style: ListStyle::Embed,
+            head_url: None,
+            low_tps: 15.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListStyle {
+    /// A discord embed, which falls back to text if the bot isn't allowed to send embeds.
+    Embed,
+    /// Plain text with code blocks.
+    Text,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct AlertsConfig {
+    /// The channel where alerts are posted, mentioning the op role.
+    pub channel_id: u64,
+    /// The minimum number of seconds between two alerts for the same rule and server.
+    pub cooldown: u64,
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct AuditConfig {
+    /// The channel where the commands run through `/run` are posted.
+    pub channel_id: Option<u64>,
+}
+
+/// Who may use which commands. Every command has a default tier, which can be overridden per
+/// command.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct PermissionsConfig {
+    /// The roles of admins, in addition to the op role.
+    pub admin_role_ids: Vec<u64>,
+    pub moderator_role_ids: Vec<u64>,
+    /// The roles of members. If this is empty, everyone is a member.
+    pub member_role_ids: Vec<u64>,
+    pub commands: Vec<CommandPermission>,
+}
+
+/// The permissions of a command, or of every subcommand of a command group (unless they have
+/// their own).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandPermission {
+    /// The command's full name, e.g. `whitelist add`.
+    pub name: String,
+    /// Replaces the command's default tier.
+    pub tier: Option<Tier>,
+    /// Roles and users that may use the command regardless of their tier.
+    pub role_ids: Vec<u64>,
+    pub user_ids: Vec<u64>,
+    /// The channels the command can be used in. If this is empty, it can be used anywhere.
+    pub channel_ids: Vec<u64>,
+}
+
+/// Each tier may use the commands of the tiers below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tier {
+    Member,
+    Moderator,
+    Admin,
+}
+
+impl Tier {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Member => "member",
+            Tier::Moderator => "moderator",
+            Tier::Admin => "admin",
+        }
+    }
+}
+
+/// Which commands the members who aren't admins may run through `/run`, and which commands need
+/// to be confirmed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RunConfig {
+    pub policies: Vec<RunPolicy>,
+    /// The commands which are only run once the invoker confirms them.
+    pub confirm: Vec<CommandPattern>,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        let pattern = |words: &[&str]| CommandPattern {
+            words: words.iter().map(|word| word.to_string()).collect(),
+        };
+
+        Self {
+            policies: vec![],
+            confirm: vec![
+                pattern(&["stop"]),
+                pattern(&["kill", "@e"]),
+                pattern(&["op"]),
+                pattern(&["deop"]),
+                pattern(&["ban-ip"]),
+                pattern(&["whitelist", "off"]),
+            ],
+        }
+    }
+}
+
+/// Lets the members of a role run the commands matching `allow`, except for those matching
+/// `deny`. Deny patterns apply to admins with the role as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunPolicy {
+    pub name: String,
+    pub role_id: u64,
+    pub allow: Vec<CommandPattern>,
+    pub deny: Vec<CommandPattern>,
+}
+
+/// Matches the commands starting with the given words, e.g. `whitelist add`. `*` matches every
+/// command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandPattern {
+    /// The lowercase words. This is empty for `*`.
+    pub words: Vec<String>,
+}
+
+/// Alerts when the TPS in `window` stays below `below` for `polls` consecutive polls, and
+/// recovers once it stays at or above `recover_above` for as long.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    pub window: TpsWindow,
+    pub below: f32,
+    pub recover_above: f32,
+    pub polls: u64,
+}
+
+impl Default for AlertRule {
+    fn default() -> Self {
+        Self {
+            window: TpsWindow::OneMinute,
+            below: 15.0,
+            recover_above: 18.0,
+            polls: 3,
+        }
+    }
+}
+
+/// The windows the server reports its TPS over, in the order they are reported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TpsWindow {
+    FiveSeconds,
+    TenSeconds,
+    OneMinute,
+    FiveMinutes,
+    FifteenMinutes,
+}
+
+impl TpsWindow {
+    const ALL: [TpsWindow; 5] = [
+        TpsWindow::FiveSeconds,
+        TpsWindow::TenSeconds,
+        TpsWindow::OneMinute,
+        TpsWindow::FiveMinutes,
+        TpsWindow::FifteenMinutes,
+    ];
+
+    /// The index of this window in `OnlineServerStatus::tps`.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TpsWindow::FiveSeconds => "5s",
+            TpsWindow::TenSeconds => "10s",
+            TpsWindow::OneMinute => "1m",
+            TpsWindow::FiveMinutes => "5m",
+            TpsWindow::FifteenMinutes => "15m",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ModerationConfig {
+    /// The moderation log channel, where every action is posted. Rules with the notify action
+    /// require it.
+    pub channel_id: Option<u64>,
+    /// The command used to rename players, with `{name}` and `{nickname}` standing in for the
+    /// player's name and new nickname.
+    pub rename_command: String,
+    /// The rules, in the order they are checked. Only the first matching rule applies.
+    pub rules: Vec<ModerationRule>,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            channel_id: None,
+            rename_command: "styled-nicknames set {name} {nickname}".into(),
+            rules: vec![ModerationRule {
+                name: "links".into(),
+                pattern: Regex::new(&format!(
+                    r"(?u)`{0}`{0}`|h{0}t{0}t{0}p{0}s?{0}:{0}/{0}/|d{0}i{0}s{0}c{0}o{0}r{0}d{0}\.{0}g{0}g",
+                    r"[^\w!-_a-~]*"
+                ))
+                .unwrap(),
+                ..ModerationRule::default()
+            }],
+        }
+    }
+}
+
+impl PartialEq for ModerationConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.channel_id == other.channel_id
+            && self.rename_command == other.rename_command
+            && self.rules.len() == other.rules.len()
+            && self.rules.iter().zip(&other.rules).all(|(a, b)| {
+                a.name == b.name
+                    && a.pattern.as_str() == b.pattern.as_str()
+                    && a.actions == b.actions
+                    && a.nickname == b.nickname
+                    && a.reason == b.reason
+            })
+    }
+}
+
+/// Applies `actions` to players whose nickname (with its formatting tags removed) matches
+/// `pattern`.
+#[derive(Clone)]
+pub struct ModerationRule {
+    pub name: String,
+    pub pattern: Regex,
+    pub actions: Vec<ModerationAction>,
+    /// The nickname players are renamed to. This is also what the list shows instead of the
+    /// offending nickname, unless the rule only notifies.
+    pub nickname: String,
+    /// The reason given when kicking or banning.
+    pub reason: String,
+}
+
+impl Default for ModerationRule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            pattern: Regex::new("$^").unwrap(),
+            actions: vec![ModerationAction::Rename, ModerationAction::Kick],
+            nickname: "I MADE BOOL SAD".into(),
+            reason: "nice try".into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModerationAction {
+    /// Replaces the player's nickname using `rename_command`.
+    Rename,
+    Kick,
+    Ban,
+    /// Does nothing but post to the moderation channel, which is done for every other action
+    /// as well.
+    Notify,
+}
+
+impl ModerationAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            ModerationAction::Rename => "rename",
+            ModerationAction::Kick => "kick",
+            ModerationAction::Ban => "ban",
+            ModerationAction::Notify => "notify",
+        }
+    }
+}
+
+pub struct ServerConfig {
+    pub name: String,
+    pub directory: String,
+    pub list_channel_id: u64,
+    /// The channel where joins, leaves and the server going online/offline are posted.
+    pub events_channel_id: Option<u64>,
+    /// The channel which is bridged with the in-game chat.
+    pub chat_channel_id: Option<u64>,
+    /// The times of day (in UTC) at which the server is restarted.
+    pub daily_restarts: Vec<TimeOfDay>,
+    pub rcon: RconConfig,
+    pub features: FeaturesConfig,
+    pub status: StatusConfig,
+    pub announcements: Option<AnnouncementsConfig>,
+    pub supervisor: Option<SupervisorConfig>,
+}
+
+#[derive(PartialEq, Eq)]
+pub struct RconConfig {
+    pub address: String,
+    pub password: String,
+}
+
+#[derive(Default, PartialEq, Eq)]
+pub struct FeaturesConfig {
+    /// Whether the server supports boolean_coercion's "/list json" protocol.
+    pub list_json: bool,
+    /// Whether the server uses EasyAuth, whose config file should be modified for offline mode users.
+    pub easyauth: bool,
+}
+
+/// Deaths and advancements, taken from the server log and posted as embeds.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AnnouncementsConfig {
+    pub channel_id: u64,
+    pub deaths: bool,
+    pub advancements: bool,
+    /// The maximum number of announcements per minute. The ones over the limit are summed up.
+    pub max_per_minute: u64,
+}
+
+/// A time of day in UTC, as the number of seconds since midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(pub u32);
+
+impl TimeOfDay {
+    /// Parses a time such as `04:30`.
+    pub fn parse(time: &str) -> Option<Self> {
+        let (hours, minutes) = time.trim().split_once(':')?;
+        let hours: u32 = hours.parse().ok()?;
+        let minutes: u32 = minutes.parse().ok()?;
+        (hours < 24 && minutes < 60).then_some(Self(hours * 3600 + minutes * 60))
+    }
+}
+
+/// A program and its arguments, which is never empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandLine(Vec<String>);
+
+impl CommandLine {
+    pub fn new(words: Vec<String>) -> Option<Self> {
+        (!words.is_empty()).then_some(Self(words))
+    }
+
+    pub fn program(&self) -> &str {
+        &self.0[0]
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.0[1..]
+    }
+}
+
+/// Makes the bot run the server's process itself.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SupervisorConfig {
+    /// The command line which starts the server, run in its directory.
+    pub command: CommandLine,
+    /// Whether the server is started along with the bot.
+    pub autostart: bool,
+    /// Whether the server is started again whenever it exits, unless it was stopped from discord.
+    pub auto_restart: bool,
+    /// How many seconds the server gets to shut down after `stop` before it is killed.
+    pub stop_timeout: u64,
+    /// The most seconds to wait before restarting a server which keeps crashing.
+    pub max_backoff: u64,
+}
+
+#[derive(PartialEq, Eq)]
+pub struct StatusConfig {
+    /// The sources that are tried, in order, when getting the status of the server.
+    pub sources: Vec<StatusSource>,
+    /// The address used for Server List Ping, i.e. the one players connect to.
+    pub slp_address: String,
+    /// The address used for the query protocol (`query.port` in `server.properties`).
+    pub query_address: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusSource {
+    /// The `list` (or `list json`) command, sent over RCON.
+    Rcon,
+    /// The Server List Ping protocol used by the multiplayer screen.
+    Slp,
+    /// The GameSpy4 query protocol, enabled using `enable-query`.
+    Query,
+}
+
+/// Returns the config path given on the command line (either as `--config <path>` or as the only
+/// argument), and whether it was given explicitly. Returns `None` if the usage was asked for using
+/// `--help`, and an error for anything else it doesn't understand.
+pub fn config_path() -> Result<Option<(PathBuf, bool)>, String> {
+    parse_args(std::env::args().skip(1))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<(PathBuf, bool)>, String> {
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        let given = match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-c" | "--config" => match args.next() {
+                Some(path) => path,
+                None => return Err(format!("{arg} has to be followed by a path")),
+            },
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ => arg,
+        };
+        if path.replace(given).is_some() {
+            return Err("only one config path can be given".into());
+        }
+    }
+
+    Ok(Some(match path {
+        Some(path) => (path.into(), true),
+        None => (DEFAULT_CONFIG_PATH.into(), false),
+    }))
+}
+
+/// Loads the config file at `path` and applies the environment variable overrides on top of it.
+/// A missing file is only an error if `explicit` is set, otherwise the configuration is read from
+/// the environment alone.
+///
+/// On failure, returns a list of every problem that was found.
+pub fn load(path: &Path, explicit: bool) -> Result<Config, Vec<String>> {
+    let root = match std::fs::read_to_string(path) {
+        Ok(string) => match toml::from_str::<Table>(&string) {
+            Ok(root) => root,
+            Err(why) => return Err(vec![format!("{} is not valid TOML: {why}", path.display())]),
+        },
+        Err(why) if explicit || why.kind() != std::io::ErrorKind::NotFound => {
+            return Err(vec![format!("couldn't read {}: {why}", path.display())]);
+        }
+        Err(_) => Table::new(),
+    };
+
+    read(root, |name| std::env::var(name).ok())
+}
+
+/// Reads the config out of `root`, after applying the environment variable overrides given by
+/// `var`.
+fn read(mut root: Table, var: impl Fn(&str) -> Option<String>) -> Result<Config, Vec<String>> {
+    apply_env_overrides(&mut root, var);
+
+    let mut reader = Reader::default();
+    let config = reader.config(&root);
+
+    match config {
+        Some(config) if reader.errors.is_empty() => Ok(config),
+        _ => Err(reader.errors),
+    }
+}
+
+/// Formats the list of problems returned by [`load`] for printing.
+pub fn format_errors(path: &Path, errors: &[String]) -> String {
+    let mut text = format!(
+        "The configuration ({} and environment variables) is invalid:\n",
+        path.display()
+    );
+    for error in errors {
+        writeln!(&mut text, "- {error}").unwrap();
+    }
+    text
+}
+
+fn apply_env_overrides(root: &mut Table, var: impl Fn(&str) -> Option<String>) {
+    for (name, path) in GLOBAL_ENV_OVERRIDES {
+        if let Some(value) = var(name) {
+            set_path(root, path, value);
+        }
+    }
+
+    let servers = root.entry("server").or_insert_with(|| Value::Array(vec![]));
+    let Value::Array(servers) = servers else {
+        // this is reported by the reader
+        return;
+    };
+
+    if let Some(names) = var("SERVERS") {
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            if !servers
+                .iter()
+                .any(|server| server_name(server) == Some(name))
+            {
+                let mut table = Table::new();
+                table.insert("name".into(), Value::String(name.into()));
+                servers.push(Value::Table(table));
+            }
+        }
+    }
+
+    let any_unprefixed = SERVER_ENV_OVERRIDES
+        .iter()
+        .any(|(name, _)| var(name).is_some());
+    if servers.is_empty() && any_unprefixed {
+        let mut table = Table::new();
+        table.insert("name".into(), Value::String(DEFAULT_SERVER_NAME.into()));
+        servers.push(Value::Table(table));
+    }
+
+    let only_server = servers.len() == 1;
+    for server in servers {
+        let Some(prefix) = server_name(server).map(str::to_uppercase) else {
+            continue;
+        };
+        let Value::Table(server) = server else {
+            continue;
+        };
+
+        for (name, path) in SERVER_ENV_OVERRIDES {
+            let value = var(&format!("{prefix}_{name}"))
+                .or_else(|| only_server.then(|| var(name)).flatten());
+            if let Some(value) = value {
+                set_path(server, path, value);
+            }
+        }
+    }
+}
+
+fn server_name(server: &Value) -> Option<&str> {
+    server.get("name").and_then(Value::as_str)
+}
+
+/// Sets the key at `path` to `value`, creating intermediate tables as needed.
+fn set_path(table: &mut Table, path: &[&str], value: String) {
+    let (key, parents) = path.split_last().unwrap();
+    let mut table = table;
+    for parent in parents {
+        let entry = table
+            .entry(*parent)
+            .or_insert_with(|| Value::Table(Table::new()));
+        let Value::Table(inner) = entry else {
+            // this is reported by the reader
+            return;
+        };
+        table = inner;
+    }
+
+    table.insert(key.to_string(), Value::String(value));
+}
+
+/// A type which can be read from a config value. Since environment variables can only be strings,
+/// every type also accepts a string representation of itself.
+trait FromValue: Sized {
+    const EXPECTED: &'static str;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_owned)
+    }
+}
+
+impl FromValue for u64 {
+    const EXPECTED: &'static str = "a positive integer";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(int) => (*int).try_into().ok(),
+            Value::String(string) => string.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for f32 {
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(float) => Some(*float as f32),
+            Value::Integer(int) => Some(*int as f32),
+            Value::String(string) => string.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for ListStyle {
+    const EXPECTED: &'static str = "either \"embed\" or \"text\"";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value.as_str()?.trim() {
+            "embed" => Some(ListStyle::Embed),
+            "text" => Some(ListStyle::Text),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Vec<ModerationAction> {
+    const EXPECTED: &'static str =
+        "a list of moderation actions (\"rename\", \"kick\", \"ban\" or \"notify\")";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let parse = |action: &str| {
+            [
+                ModerationAction::Rename,
+                ModerationAction::Kick,
+                ModerationAction::Ban,
+                ModerationAction::Notify,
+            ]
+            .into_iter()
+            .find(|candidate| candidate.name() == action.trim())
+        };
+
+        let actions = match value {
+            Value::Array(actions) => actions
+                .iter()
+                .map(|action| action.as_str().and_then(parse))
+                .collect::<Option<Vec<_>>>()?,
+            Value::String(actions) => actions.split(',').map(parse).collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        (!actions.is_empty()).then_some(actions)
+    }
+}
+
+impl FromValue for TpsWindow {
+    const EXPECTED: &'static str = "one of \"5s\", \"10s\", \"1m\", \"5m\" or \"15m\"";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let name = value.as_str()?.trim();
+        TpsWindow::ALL
+            .into_iter()
+            .find(|window| window.name() == name)
+    }
+}
+
+impl FromValue for Vec<u64> {
+    const EXPECTED: &'static str = "a list of IDs";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(ids) => ids.iter().map(u64::from_value).collect(),
+            // environment variables are comma-separated
+            Value::String(ids) => ids
+                .split(',')
+                .filter(|id| !id.trim().is_empty())
+                .map(|id| id.trim().parse().ok())
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Tier {
+    const EXPECTED: &'static str = "one of \"member\", \"moderator\" or \"admin\"";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let name = value.as_str()?.trim();
+        [Tier::Member, Tier::Moderator, Tier::Admin]
+            .into_iter()
+            .find(|tier| tier.name() == name)
+    }
+}
+
+impl FromValue for Vec<CommandPattern> {
+    const EXPECTED: &'static str = "a list of commands, e.g. [\"kick\", \"whitelist add\"]";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let parse = |pattern: &str| {
+            let pattern = pattern.trim().trim_start_matches('/');
+            let words = match pattern {
+                "" => return None,
+                "*" => vec![],
+                _ => pattern.split_whitespace().map(str::to_lowercase).collect(),
+            };
+            Some(CommandPattern { words })
+        };
+
+        match value {
+            Value::Array(patterns) => patterns
+                .iter()
+                .map(|pattern| pattern.as_str().and_then(parse))
+                .collect(),
+            Value::String(patterns) => patterns.split(',').map(parse).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for CommandLine {
+    const EXPECTED: &'static str =
+        "a command line, e.g. [\"java\", \"-jar\", \"server.jar\", \"nogui\"]";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let words: Vec<String> = match value {
+            Value::Array(words) => words
+                .iter()
+                .map(String::from_value)
+                .collect::<Option<_>>()?,
+            // environment variables are split on whitespace
+            Value::String(words) => words.split_whitespace().map(str::to_owned).collect(),
+            _ => return None,
+        };
+
+        CommandLine::new(words)
+    }
+}
+
+impl FromValue for Vec<TimeOfDay> {
+    const EXPECTED: &'static str = "a list of times in UTC, e.g. [\"04:00\", \"16:00\"]";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let mut times: Vec<TimeOfDay> = match value {
+            Value::Array(times) => times
+                .iter()
+                .map(|time| time.as_str().and_then(TimeOfDay::parse))
+                .collect::<Option<_>>()?,
+            // environment variables are comma-separated
+            Value::String(times) => times
+                .split(',')
+                .filter(|time| !time.trim().is_empty())
+                .map(TimeOfDay::parse)
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        times.sort_unstable();
+        times.dedup();
+        Some(times)
+    }
+}
+
+impl FromValue for Regex {
+    const EXPECTED: &'static str = "a valid regular expression";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_str().and_then(|string| Regex::new(string).ok())
+    }
+}
+
+impl FromValue for Vec<StatusSource> {
+    const EXPECTED: &'static str = "a list of status sources (\"rcon\", \"slp\" or \"query\")";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let parse = |source: &str| match source.trim() {
+            "rcon" => Some(StatusSource::Rcon),
+            "slp" => Some(StatusSource::Slp),
+            "query" => Some(StatusSource::Query),
+            _ => None,
+        };
+
+        let sources = match value {
+            Value::Array(sources) => sources
+                .iter()
+                .map(|source| source.as_str().and_then(parse))
+                .collect::<Option<Vec<_>>>()?,
+            // environment variables are comma-separated
+            Value::String(sources) => sources.split(',').map(parse).collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        (!sources.is_empty()).then_some(sources)
+    }
+}
+
+impl FromValue for bool {
+    const EXPECTED: &'static str = "a boolean";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(boolean) => Some(*boolean),
+            // environment variables used to enable a feature just by being set, whatever their
+            // value, so anything other than an explicit "false" still does
+            Value::String(string) => {
+                let string = string.trim();
+                Some(!(string.eq_ignore_ascii_case("false") || string == "0"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads the typed config out of the TOML tree, collecting every problem along the way instead of
+/// stopping at the first one.
+#[derive(Default)]
+struct Reader {
+    errors: Vec<String>,
+}
+
+impl Reader {
+    fn config(&mut self, root: &Table) -> Option<Config> {
+        let discord = self.table(root, "", "discord", true);
+        let discord = discord.and_then(|table| self.discord(table));
+
+        let database = self.table(root, "", "database", false);
+        let database = database.map(|table| self.database(table));
+
+        let moderation = self.table(root, "", "moderation", false);
+        let moderation = moderation.map(|table| self.moderation(table));
+
+        let storage = self.table(root, "", "storage", false);
+        let storage = storage.map(|table| StorageConfig {
+            path: self
+                .get(table, "storage.", "path")
+                .unwrap_or_else(|| StorageConfig::default().path),
+        });
+
+        let list = self.table(root, "", "list", false);
+        let list = list.map(|table| self.list(table));
+
+        let alerts = self.table(root, "", "alerts", false);
+        let alerts = alerts.map(|table| self.alerts(table));
+
+        let audit = self.table(root, "", "audit", false);
+        let audit = audit.map(|table| AuditConfig {
+            channel_id: self.get(table, "audit.", "channel_id"),
+        });
+
+        let run = self.table(root, "", "run", false);
+        let run = run.map(|table| self.run(table));
+
+        let permissions = self.table(root, "", "permissions", false);
+        let permissions = permissions.map(|table| self.permissions(table));
+
+        let servers = match root.get("server") {
+            Some(Value::Array(servers)) if !servers.is_empty() => servers
+                .iter()
+                .enumerate()
+                .map(|(i, server)| self.server(i, server))
+                .collect::<Vec<_>>(),
+            Some(Value::Array(_)) | None => {
+                self.errors
+                    .push("no servers are configured (add a [[server]] entry)".into());
+                vec![]
+            }
+            Some(_) => {
+                self.errors
+                    .push("`server` should be an array of tables ([[server]])".into());
+                vec![]
+            }
+        };
+
+        let mut names: Vec<&str> = servers.iter().flatten().map(|s| &*s.name).collect();
+        names.sort_unstable();
+        for (a, b) in names.iter().zip(names.iter().skip(1)) {
+            if a == b {
+                self.errors
+                    .push(format!("there is more than one server called `{a}`"));
+            }
+        }
+
+        Some(Config {
+            discord: discord?,
+            database: database.flatten(),
+            moderation: moderation.unwrap_or_default(),
+            storage: storage.unwrap_or_default(),
+            list: list.unwrap_or_default(),
+            alerts: alerts.flatten(),
+            audit: audit.unwrap_or_default(),
+            run: run.unwrap_or_default(),
+            permissions: permissions.unwrap_or_default(),
+            servers: servers.into_iter().collect::<Option<_>>()?,
+        })
+    }
+
+    fn discord(&mut self, table: &Table) -> Option<DiscordConfig> {
+        let token = self.require(table, "discord.", "token");
+        let op_role_id = self.require(table, "discord.", "op_role_id");
+
+        Some(DiscordConfig {
+            token: token?,
+            op_role_id: op_role_id?,
+        })
+    }
+
+    fn database(&mut self, table: &Table) -> Option<DatabaseConfig> {
+        let username = self.require(table, "database.", "username");
+        let admin_endpoint = self.require(table, "database.", "admin_endpoint");
+        let admin_password = self.require(table, "database.", "admin_password");
+        let user_endpoint = self.require(table, "database.", "user_endpoint");
+        let user_password = self.require(table, "database.", "user_password");
+
+        Some(DatabaseConfig {
+            username: username?,
+            admin_endpoint: admin_endpoint?,
+            admin_password: admin_password?,
+            user_endpoint: user_endpoint?,
+            user_password: user_password?,
+        })
+    }
+
+    fn moderation(&mut self, table: &Table) -> ModerationConfig {
+        let default = ModerationConfig::default();
+        let channel_id = self.get(table, "moderation.", "channel_id");
+        let rename_command = self.get(table, "moderation.", "rename_command");
+
+        // `nickname_regex` predates the rules, and is a shorthand for a single rule with the
+        // default actions
+        let nickname_regex: Option<Regex> = self.get(table, "moderation.", "nickname_regex");
+        let rules = match (table.get("rule"), nickname_regex) {
+            (Some(_), Some(_)) => {
+                self.errors.push(
+                    "`moderation.nickname_regex` can't be used together with `moderation.rule`"
+                        .into(),
+                );
+                vec![]
+            }
+            (None, Some(pattern)) => vec![ModerationRule {
+                name: "nickname_regex".into(),
+                pattern,
+                ..ModerationRule::default()
+            }],
+            (None, None) => default.rules,
+            (Some(Value::Array(rules)), None) => rules
+                .iter()
+                .enumerate()
+                .filter_map(|(i, rule)| self.moderation_rule(i, rule))
+                .collect(),
+            (Some(_), None) => {
+                self.errors.push(
+                    "`moderation.rule` should be an array of tables ([[moderation.rule]])".into(),
+                );
+                vec![]
+            }
+        };
+
+        if channel_id.is_none()
+            && rules
+                .iter()
+                .any(|rule| rule.actions.contains(&ModerationAction::Notify))
+        {
+            self.errors.push(
+                "`moderation.channel_id` is missing, but some rules use the notify action".into(),
+            );
+        }
+
+        ModerationConfig {
+            channel_id,
+            rename_command: rename_command.unwrap_or(default.rename_command),
+            rules,
+        }
+    }
+
+    fn moderation_rule(&mut self, index: usize, rule: &Value) -> Option<ModerationRule> {
+        let prefix = format!("moderation.rule[{index}].");
+        let Value::Table(table) = rule else {
+            self.errors
+                .push(format!("`moderation.rule[{index}]` should be a table"));
+            return None;
+        };
+
+        let default = ModerationRule::default();
+        let name = self.get(table, &prefix, "name");
+        let pattern = self.require(table, &prefix, "pattern");
+        let actions = self.get(table, &prefix, "actions");
+        let nickname = self.get(table, &prefix, "nickname");
+        let reason = self.get(table, &prefix, "reason");
+
+        Some(ModerationRule {
+            name: name.unwrap_or_else(|| format!("rule {}", index + 1)),
+            pattern: pattern?,
+            actions: actions.unwrap_or(default.actions),
+            nickname: nickname.unwrap_or(default.nickname),
+            reason: reason.unwrap_or(default.reason),
+        })
+    }
+
+    fn permissions(&mut self, table: &Table) -> PermissionsConfig {
+        let prefix = "permissions.";
+        let commands = match table.get("command") {
+            Some(Value::Array(commands)) => commands
+                .iter()
+                .enumerate()
+                .filter_map(|(i, command)| self.command_permission(i, command))
+                .collect(),
+            None => vec![],
+            Some(_) => {
+                self.errors.push(
+                    "`permissions.command` should be an array of tables ([[permissions.command]])"
+                        .into(),
+                );
+                vec![]
+            }
+        };
+
+        PermissionsConfig {
+            admin_role_ids: self
+                .get(table, prefix, "admin_role_ids")
+                .unwrap_or_default(),
+            moderator_role_ids: self
+                .get(table, prefix, "moderator_role_ids")
+                .unwrap_or_default(),
+            member_role_ids: self
+                .get(table, prefix, "member_role_ids")
+                .unwrap_or_default(),
+            commands,
+        }
+    }
+
+    fn command_permission(&mut self, index: usize, command: &Value) -> Option<CommandPermission> {
+        let prefix = format!("permissions.command[{index}].");
+        let Value::Table(table) = command else {
+            self.errors
+                .push(format!("`permissions.command[{index}]` should be a table"));
+            return None;
+        };
+
+        let name: Option<String> = self.require(table, &prefix, "name");
+        let tier = self.get(table, &prefix, "tier");
+        let role_ids = self.get(table, &prefix, "role_ids");
+        let user_ids = self.get(table, &prefix, "user_ids");
+        let channel_ids = self.get(table, &prefix, "channel_ids");
+
+        Some(CommandPermission {
+            // `/whitelist add` and `whitelist  add` both refer to the same command
+            name: name?
+                .trim_start_matches('/')
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            tier,
+            role_ids: role_ids.unwrap_or_default(),
+            user_ids: user_ids.unwrap_or_default(),
+            channel_ids: channel_ids.unwrap_or_default(),
+        })
+    }
+
+    fn run(&mut self, table: &Table) -> RunConfig {
+        let policies = match table.get("policy") {
+            Some(Value::Array(policies)) => policies
+                .iter()
+                .enumerate()
+                .filter_map(|(i, policy)| self.run_policy(i, policy))
+                .collect(),
+            None => vec![],
+            Some(_) => {
+                self.errors
+                    .push("`run.policy` should be an array of tables ([[run.policy]])".into());
+                vec![]
+            }
+        };
+
+        RunConfig {
+            policies,
+            confirm: self
+                .get(table, "run.", "confirm")
+                .unwrap_or_else(|| RunConfig::default().confirm),
+        }
+    }
+
+    fn run_policy(&mut self, index: usize, policy: &Value) -> Option<RunPolicy> {
+        let prefix = format!("run.policy[{index}].");
+        let Value::Table(table) = policy else {
+            self.errors
+                .push(format!("`run.policy[{index}]` should be a table"));
+            return None;
+        };
+
+        let name = self.get(table, &prefix, "name");
+        let role_id = self.require(table, &prefix, "role_id");
+        let allow = self.get(table, &prefix, "allow");
+        let deny = self.get(table, &prefix, "deny");
+
+        Some(RunPolicy {
+            name: name.unwrap_or_else(|| format!("policy {}", index + 1)),
+            role_id: role_id?,
+            allow: allow.unwrap_or_default(),
+            deny: deny.unwrap_or_default(),
+        })
+    }
+
+    fn list(&mut self, table: &Table) -> ListConfig {
+        let default = ListConfig::default();
+
+        ListConfig {
+            style: self.get(table, "list.", "style").unwrap_or(default.style),
+            head_url: self.get(table, "list.", "head_url"),
+            low_tps: self
+                .get(table, "list.", "low_tps")
+                .unwrap_or(default.low_tps),
+        }
+    }
+
+    fn alerts(&mut self, table: &Table) -> Option<AlertsConfig> {
+        let channel_id = self.require(table, "alerts.", "channel_id");
+        let cooldown = self.get(table, "alerts.", "cooldown").unwrap_or(600);
+
+        let rules = match table.get("rule") {
+            Some(Value::Array(rules)) => rules
+                .iter()
+                .enumerate()
+                .map(|(i, rule)| self.alert_rule(i, rule))
+                .collect::<Vec<_>>(),
+            None => vec![Some(AlertRule::default())],
+            Some(_) => {
+                self.errors
+                    .push("`alerts.rule` should be an array of tables ([[alerts.rule]])".into());
+                vec![]
+            }
+        };
+
+        Some(AlertsConfig {
+            channel_id: channel_id?,
+            cooldown,
+            rules: rules.into_iter().collect::<Option<_>>()?,
+        })
+    }
+
+    fn alert_rule(&mut self, index: usize, rule: &Value) -> Option<AlertRule> {
+        let prefix = format!("alerts.rule[{index}].");
+        let Value::Table(table) = rule else {
+            self.errors
+                .push(format!("`alerts.rule[{index}]` should be a table"));
+            return None;
+        };
+
+        let default = AlertRule::default();
+        let window = self.get(table, &prefix, "window");
+        let below: f32 = self.require(table, &prefix, "below")?;
+        let recover_above = self.get(table, &prefix, "recover_above").unwrap_or(below);
+        let polls = self.get(table, &prefix, "polls").unwrap_or(default.polls);
+
+        if recover_above < below {
+            self.errors.push(format!(
+                "`{prefix}recover_above` ({recover_above}) should be at least `{prefix}below` ({below})"
+            ));
+        }
+        if polls == 0 {
+            self.errors
+                .push(format!("`{prefix}polls` should be at least 1"));
+        }
+
+        Some(AlertRule {
+            window: window.unwrap_or(default.window),
+            below,
+            recover_above,
+            polls,
+        })
+    }
+
+    fn server(&mut self, index: usize, server: &Value) -> Option<ServerConfig> {
+        let Value::Table(table) = server else {
+            self.errors
+                .push(format!("`server[{index}]` should be a table"));
+            return None;
+        };
+
+        let name: Option<String> = self.require(table, &format!("server[{index}]."), "name");
+        let prefix = match &name {
+            Some(name) => format!("server.{name}."),
+            None => format!("server[{index}]."),
+        };
+
+        let directory = self.require(table, &prefix, "directory");
+        let list_channel_id = self.require(table, &prefix, "list_channel_id");
+        let events_channel_id = self.get(table, &prefix, "events_channel_id");
+        let chat_channel_id = self.get(table, &prefix, "chat_channel_id");
+        let daily_restarts = self.get(table, &prefix, "daily_restarts");
+
+        let rcon = self.table(table, &prefix, "rcon", true);
+        let rcon = rcon.and_then(|rcon| {
+            let prefix = format!("{prefix}rcon.");
+            let address = self.require(rcon, &prefix, "address");
+            let password = self.require(rcon, &prefix, "password");
+            Some(RconConfig {
+                address: address?,
+                password: password?,
+            })
+        });
+
+        let features = self.table(table, &prefix, "features", false);
+        let features = features.map(|features| {
+            let prefix = format!("{prefix}features.");
+            FeaturesConfig {
+                list_json: self.get(features, &prefix, "list_json").unwrap_or(false),
+                easyauth: self.get(features, &prefix, "easyauth").unwrap_or(false),
+            }
+        });
+
+        let status = self.table(table, &prefix, "status", false);
+        let status = status.map(|status| {
+            let prefix = format!("{prefix}status.");
+            let sources = self.get(status, &prefix, "sources");
+            let slp_address = self.get(status, &prefix, "slp_address");
+            let query_address = self.get(status, &prefix, "query_address");
+            (sources, slp_address, query_address)
+        });
+        let (sources, slp_address, query_address) = status.unwrap_or_default();
+
+        let announcements = self.table(table, &prefix, "announcements", false);
+        let announcements = announcements.and_then(|announcements| {
+            let prefix = format!("{prefix}announcements.");
+            Some(AnnouncementsConfig {
+                channel_id: self.require(announcements, &prefix, "channel_id")?,
+                deaths: self.get(announcements, &prefix, "deaths").unwrap_or(true),
+                advancements: self
+                    .get(announcements, &prefix, "advancements")
+                    .unwrap_or(true),
+                max_per_minute: self
+                    .get(announcements, &prefix, "max_per_minute")
+                    .unwrap_or(10),
+            })
+        });
+        let supervisor = self.table(table, &prefix, "supervisor", false);
+        let supervisor = supervisor.and_then(|supervisor| {
+            let prefix = format!("{prefix}supervisor.");
+            Some(SupervisorConfig {
+                command: self.require(supervisor, &prefix, "command")?,
+                autostart: self.get(supervisor, &prefix, "autostart").unwrap_or(true),
+                auto_restart: self
+                    .get(supervisor, &prefix, "auto_restart")
+                    .unwrap_or(true),
+                stop_timeout: self.get(supervisor, &prefix, "stop_timeout").unwrap_or(60),
+                max_backoff: self.get(supervisor, &prefix, "max_backoff").unwrap_or(300),
+            })
+        });
+
+        // everything was read by now, so a bad rcon table doesn't hide the errors in the rest
+        let rcon = rcon?;
+        let status = StatusConfig {
+            sources: sources.unwrap_or_else(|| vec![StatusSource::Rcon]),
+            slp_address: slp_address.unwrap_or_else(|| default_game_address(&rcon.address)),
+            query_address: query_address.unwrap_or_else(|| default_game_address(&rcon.address)),
+        };
+
+        Some(ServerConfig {
+            name: name?,
+            directory: directory?,
+            list_channel_id: list_channel_id?,
+            events_channel_id,
+            chat_channel_id,
+            daily_restarts: daily_restarts.unwrap_or_default(),
+            rcon,
+            features: features.unwrap_or_default(),
+            status,
+            announcements,
+            supervisor,
+        })
+    }
+
+    /// Returns the table at `key`. A missing required table is treated as an empty one, so that
+    /// each of its missing keys gets reported.
+    fn table<'a>(
+        &mut self,
+        parent: &'a Table,
+        prefix: &str,
+        key: &str,
+        required: bool,
+    ) -> Option<&'a Table> {
+        match parent.get(key) {
+            Some(Value::Table(table)) => Some(table),
+            Some(_) => {
+                self.errors
+                    .push(format!("`{prefix}{key}` should be a table"));
+                None
+            }
+            None if required => Some(&EMPTY_TABLE),
+            None => None,
+        }
+    }
+
+    fn get<T: FromValue>(&mut self, table: &Table, prefix: &str, key: &str) -> Option<T> {
+        let value = table.get(key)?;
+        let parsed = T::from_value(value);
+        if parsed.is_none() {
+            self.errors.push(format!(
+                "`{prefix}{key}` should be {}, but it is `{value}`",
+                T::EXPECTED
+            ));
+        }
+        parsed
+    }
+
+    fn require<T: FromValue>(&mut self, table: &Table, prefix: &str, key: &str) -> Option<T> {
+        if !table.contains_key(key) {
+            let mut error = format!("`{prefix}{key}` is missing");
+            if let Some(env) = env_name(prefix, key) {
+                write!(&mut error, " (it can also be set using {env})").unwrap();
+            }
+            self.errors.push(error);
+            return None;
+        }
+        self.get(table, prefix, key)
+    }
+}
+
+/// Servers usually listen for players (and queries) on the same host as RCON, using the default
+/// port.
+fn default_game_address(rcon_address: &str) -> String {
+    let host = rcon_address
+        .rsplit_once(':')
+        .map_or(rcon_address, |(host, _)| host);
+    format!("{host}:25565")
+}
+
+/// Returns the name of the environment variable which overrides the given key, if any.
+fn env_name(prefix: &str, key: &str) -> Option<String> {
+    let mut path: Vec<&str> = prefix.split('.').filter(|s| !s.is_empty()).collect();
+    path.push(key);
+
+    if let Some((name, _)) = GLOBAL_ENV_OVERRIDES.iter().find(|(_, p)| *p == path) {
+        return Some(name.to_string());
+    }
+
+    match path.as_slice() {
+        ["server", server, rest @ ..] => SERVER_ENV_OVERRIDES
+            .iter()
+            .find(|(_, p)| *p == rest)
+            .map(|(name, _)| format!("{}_{name}", server.to_uppercase())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn read_str(config: &str, vars: &[(&str, &str)]) -> Result<Config, Vec<String>> {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        read(toml::from_str(config).unwrap(), |name| {
+            vars.get(name).map(|&value| value.to_owned())
+        })
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let config = r#"
+            [discord]
+            op_role_id = "not an id"
+
+            [list]
+            style = "fancy"
+
+            [[server]]
+            name = "survival"
+            list_channel_id = 1
+            rcon = "localhost"
+
+            [[server]]
+            name = "creative"
+            directory = "creative"
+            list_channel_id = -1
+            daily_restarts = ["25:00"]
+            [server.rcon]
+            address = "127.0.0.1:25576"
+        "#;
+        let Err(errors) = read_str(config, &[]) else {
+            panic!("the config was accepted");
+        };
+
+        let expected = [
+            "`discord.token` is missing (it can also be set using DISCORD_TOKEN)",
+            "`discord.op_role_id` should be a positive integer, but it is `\"not an id\"`",
+            "`list.style`",
+            "`server.survival.directory` is missing (it can also be set using SURVIVAL_SERVER_DIR)",
+            "`server.survival.rcon` should be a table",
+            "`server.creative.list_channel_id` should be a positive integer, but it is `-1`",
+            "`server.creative.daily_restarts`",
+            "`server.creative.rcon.password` is missing (it can also be set using CREATIVE_RCON_PASS)",
+        ];
+        for expected in expected {
+            assert!(
+                errors.iter().any(|error| error.starts_with(expected)),
+                "{expected} is missing from {errors:#?}"
+            );
+        }
+        assert_eq!(errors.len(), expected.len(), "{errors:#?}");
+    }
+
+    #[test]
+    fn environment_only() {
+        let config = read_str(
+            "",
+            &[
+                ("DISCORD_TOKEN", "token"),
+                ("OP_ROLE_ID", "1"),
+                ("ADMIN_ROLE_IDS", "2, 3,"),
+                ("RCON_ADDR", "127.0.0.1:25575"),
+                ("RCON_PASS", "hunter2"),
+                ("LIST_CHANNEL_ID", "4"),
+                ("SERVER_DIR", "/srv/minecraft"),
+                ("HAS_LIST_JSON", "1"),
+                ("HAS_EASYAUTH", "false"),
+                ("SERVER_COMMAND", "java -jar server.jar nogui"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.discord.token, "token");
+        assert_eq!(config.discord.op_role_id, 1);
+        assert_eq!(config.permissions.admin_role_ids, [2, 3]);
+
+        let [server] = &config.servers[..] else {
+            panic!("there should be exactly one server");
+        };
+        assert_eq!(server.name, DEFAULT_SERVER_NAME);
+        assert_eq!(server.rcon.address, "127.0.0.1:25575");
+        assert_eq!(server.status.slp_address, "127.0.0.1:25565");
+        assert_eq!(server.list_channel_id, 4);
+        assert!(server.features.list_json);
+        assert!(!server.features.easyauth);
+        let command = &server.supervisor.as_ref().unwrap().command;
+        assert_eq!(
+            (command.program(), command.args()),
+            (
+                "java",
+                &["-jar".to_owned(), "server.jar".into(), "nogui".into()][..]
+            )
+        );
+    }
+
+    #[test]
+    fn environment_overrides() {
+        let config = r#"
+            [discord]
+            token = "from the file"
+            op_role_id = 1
+
+            [[server]]
+            name = "survival"
+            directory = "survival"
+            list_channel_id = 2
+            rcon = { address = "127.0.0.1:25575", password = "hunter2" }
+
+            [[server]]
+            name = "creative"
+            directory = "creative"
+            list_channel_id = 3
+            rcon = { address = "127.0.0.1:25576", password = "hunter2" }
+        "#;
+        let config = read_str(
+            config,
+            &[
+                ("DISCORD_TOKEN", "from the environment"),
+                ("CREATIVE_RCON_PASS", "hunter3"),
+                // there's more than one server, so this is ignored
+                ("LIST_CHANNEL_ID", "4"),
+                ("SERVERS", "survival, lobby"),
+                ("LOBBY_SERVER_DIR", "lobby"),
+                ("LOBBY_LIST_CHANNEL_ID", "5"),
+                ("LOBBY_RCON_ADDR", "127.0.0.1:25577"),
+                ("LOBBY_RCON_PASS", "hunter4"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.discord.token, "from the environment");
+        let servers: Vec<_> = config
+            .servers
+            .iter()
+            .map(|server| {
+                (
+                    &*server.name,
+                    server.list_channel_id,
+                    &*server.rcon.password,
+                )
+            })
+            .collect();
+        assert_eq!(
+            servers,
+            [
+                ("survival", 2, "hunter2"),
+                ("creative", 3, "hunter3"),
+                ("lobby", 5, "hunter4")
+            ]
+        );
+    }
+
+    #[test]
+    fn args() {
+        let parse = |args: &[&str]| parse_args(args.iter().map(|&arg| arg.to_owned()));
+
+        assert_eq!(parse(&[]), Ok(Some((DEFAULT_CONFIG_PATH.into(), false))));
+        assert_eq!(parse(&["a.toml"]), Ok(Some(("a.toml".into(), true))));
+        assert_eq!(
+            parse(&["--config", "-weird.toml"]),
+            Ok(Some(("-weird.toml".into(), true)))
+        );
+        assert_eq!(parse(&["a.toml", "--help"]), Ok(None));
+        assert!(parse(&["--verbose"]).is_err());
+        assert!(parse(&["-c"]).is_err());
+        assert!(parse(&["a.toml", "b.toml"]).is_err());
+    }
+
+    #[test]
+    fn strings() {
+        let string = |string: &str| Value::String(string.into());
+
+        assert_eq!(bool::from_value(&string("true")), Some(true));
+        assert_eq!(bool::from_value(&string("yes")), Some(true));
+        assert_eq!(bool::from_value(&string(" FALSE ")), Some(false));
+        assert_eq!(bool::from_value(&string("0")), Some(false));
+
+        assert_eq!(
+            Vec::<u64>::from_value(&string("1,2, 3")),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(Vec::<u64>::from_value(&string("")), Some(vec![]));
+        assert_eq!(Vec::<u64>::from_value(&string("1,two")), None);
+
+        assert_eq!(
+            CommandLine::from_value(&string(" sh  -c ")).unwrap().args(),
+            ["-c"]
+        );
+        assert_eq!(CommandLine::from_value(&string(" ")), None);
+    }
+}
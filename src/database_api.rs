@@ -166,6 +166,7 @@ impl MonadApi {
         }
     }
 
+    #[allow(clippy::result_large_err)] // the error type comes from uuid_mc
     pub async fn insert_user_with_name(
         &self,
         discord_id: UserId,
@@ -0,0 +1,312 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude::{CreateMessage, Http, MessageBuilder};
+use tokio::sync::broadcast;
+use uuid_mc::PlayerUuid;
+
+use crate::server_status::{PlayerData, ServerStatus};
+use crate::Data;
+
+/// How many events can be buffered before slow subscribers start missing them.
+pub const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct ServerEvent {
+    /// The name of the server this event happened on.
+    pub server: Arc<str>,
+    pub kind: ServerEventKind,
+    /// Set for events which describe the state the server was in when the bot started watching
+    /// it, rather than an actual change. Subscribers that only care about changes should ignore
+    /// these.
+    pub initial: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerEventKind {
+    PlayerJoined(Player),
+    PlayerLeft(Player),
+    ServerWentOnline,
+    ServerWentOffline,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    pub name: String,
+    pub uuid: Option<PlayerUuid>,
+}
+
+impl Player {
+    fn is_same(&self, other: &Player) -> bool {
+        match (self.uuid, other.uuid) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.name == other.name,
+        }
+    }
+}
+
+impl From<&PlayerData> for Player {
+    fn from(data: &PlayerData) -> Self {
+        Self {
+            name: data.name.clone(),
+            uuid: data.uuid,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Snapshot {
+    Offline,
+    Online(Vec<Player>),
+}
+
+/// Turns successive status polls of a single server into events.
+pub struct StatusTracker {
+    server: Arc<str>,
+    previous: Option<Snapshot>,
+}
+
+impl StatusTracker {
+    pub fn new(server: Arc<str>) -> Self {
+        Self {
+            server,
+            previous: None,
+        }
+    }
+
+    /// Compares `status` against the previous one and returns the events that happened in between.
+    /// The first call returns events describing the initial state.
+    ///
+    /// Some status sources only report a sample of the players. While the player list is
+    /// incomplete, the previous one is kept, so no join/leave events are generated.
+    pub fn update(&mut self, status: &ServerStatus) -> Vec<ServerEvent> {
+        let current = match (status, &self.previous) {
+            (ServerStatus::Offline, _) => Snapshot::Offline,
+            (ServerStatus::Online(status), Some(Snapshot::Online(previous)))
+                if status.list.len() != status.current_players as usize =>
+            {
+                Snapshot::Online(previous.clone())
+            }
+            (ServerStatus::Online(status), _) => {
+                Snapshot::Online(status.list.iter().map(Player::from).collect())
+            }
+        };
+
+        let mut events = vec![];
+        match (&self.previous, &current) {
+            (Some(Snapshot::Offline) | None, Snapshot::Online(players)) => {
+                events.push(ServerEventKind::ServerWentOnline);
+                events.extend(players.iter().cloned().map(ServerEventKind::PlayerJoined));
+            }
+            (Some(Snapshot::Online(previous)), Snapshot::Online(players)) => {
+                events.extend(
+                    previous
+                        .iter()
+                        .filter(|old| !players.iter().any(|new| new.is_same(old)))
+                        .cloned()
+                        .map(ServerEventKind::PlayerLeft),
+                );
+                events.extend(
+                    players
+                        .iter()
+                        .filter(|new| !previous.iter().any(|old| old.is_same(new)))
+                        .cloned()
+                        .map(ServerEventKind::PlayerJoined),
+                );
+            }
+            (Some(Snapshot::Online(previous)), Snapshot::Offline) => {
+                events.extend(previous.iter().cloned().map(ServerEventKind::PlayerLeft));
+                events.push(ServerEventKind::ServerWentOffline);
+            }
+            (None, Snapshot::Offline) => events.push(ServerEventKind::ServerWentOffline),
+            (Some(Snapshot::Offline), Snapshot::Offline) => {}
+        }
+
+        let initial = self.previous.is_none();
+        self.previous = Some(current);
+
+        events
+            .into_iter()
+            .map(|kind| ServerEvent {
+                server: Arc::clone(&self.server),
+                kind,
+                initial,
+            })
+            .collect()
+    }
+}
+
+/// Posts every event (except the initial ones) to its server's events channel, if it has one.
+pub async fn post_events(
+    data: Data,
+    http: Arc<Http>,
+    mut events: broadcast::Receiver<ServerEvent>,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                log::warn!("Missed {count} events while posting them to discord");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        if event.initial {
+            continue;
+        }
+
+        let settings = data.settings();
+        let Some(channel_id) = settings
+            .server(&event.server)
+            .and_then(|server| server.events_channel_id)
+        else {
+            continue;
+        };
+
+        let mut message = MessageBuilder::new();
+        match &event.kind {
+            ServerEventKind::PlayerJoined(player) => message
+                .push_bold_safe(&player.name)
+                .push_safe(format!(" joined {}.", event.server)),
+            ServerEventKind::PlayerLeft(player) => message
+                .push_bold_safe(&player.name)
+                .push_safe(format!(" left {}.", event.server)),
+            ServerEventKind::ServerWentOnline => {
+                message.push_safe(format!("{} is now online.", event.server))
+            }
+            ServerEventKind::ServerWentOffline => {
+                message.push_safe(format!("{} is now offline.", event.server))
+            }
+        };
+
+        if let Err(why) = channel_id
+            .send_message(&http, CreateMessage::new().content(message.build()))
+            .await
+        {
+            eprintln!("Couldn't post event to {channel_id}: {why}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rcon::MockRcon;
+    use crate::server_status::{get_server_status, OnlineServerStatus};
+
+    fn online(players: &[&str], current_players: i32) -> ServerStatus {
+        ServerStatus::Online(OnlineServerStatus {
+            current_players,
+            max_players: 20,
+            list: players
+                .iter()
+                .map(|name| PlayerData {
+                    name: name.to_string(),
+                    nickname: None,
+                    uuid: None,
+                })
+                .collect(),
+            tps: None,
+            mspt: None,
+            motd: None,
+            version: None,
+            protocol: None,
+            map: None,
+            game_type: None,
+            plugins: None,
+        })
+    }
+
+    fn player(name: &str) -> Player {
+        Player {
+            name: name.to_string(),
+            uuid: None,
+        }
+    }
+
+    fn kinds(events: Vec<ServerEvent>) -> Vec<ServerEventKind> {
+        events.into_iter().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn initial_state() {
+        let mut tracker = StatusTracker::new("test".into());
+        let events = tracker.update(&online(&["alex"], 1));
+
+        assert!(events.iter().all(|event| event.initial));
+        assert_eq!(
+            kinds(events),
+            [
+                ServerEventKind::ServerWentOnline,
+                ServerEventKind::PlayerJoined(player("alex"))
+            ]
+        );
+
+        let events = tracker.update(&online(&["alex"], 1));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn joins_and_leaves() {
+        let mut tracker = StatusTracker::new("test".into());
+        tracker.update(&online(&["alex", "steve"], 2));
+
+        let events = tracker.update(&online(&["notch", "steve"], 2));
+        assert!(events.iter().all(|event| !event.initial));
+        assert_eq!(
+            kinds(events),
+            [
+                ServerEventKind::PlayerLeft(player("alex")),
+                ServerEventKind::PlayerJoined(player("notch"))
+            ]
+        );
+    }
+
+    #[test]
+    fn going_offline_and_online() {
+        let mut tracker = StatusTracker::new("test".into());
+        tracker.update(&online(&["alex"], 1));
+
+        assert_eq!(
+            kinds(tracker.update(&ServerStatus::Offline)),
+            [
+                ServerEventKind::PlayerLeft(player("alex")),
+                ServerEventKind::ServerWentOffline
+            ]
+        );
+        assert!(tracker.update(&ServerStatus::Offline).is_empty());
+        assert_eq!(
+            kinds(tracker.update(&online(&[], 0))),
+            [ServerEventKind::ServerWentOnline]
+        );
+    }
+
+    #[tokio::test]
+    async fn last_player_leaving() {
+        let rcon = MockRcon::start("hunter2").await;
+        let server = rcon.server(false, "");
+        let mut tracker = StatusTracker::new("test".into());
+
+        rcon.respond("list", "There are 1 of a max of 20 players online: alex");
+        tracker.update(&get_server_status(&server).await.unwrap());
+
+        rcon.respond("list", "There are 0 of a max of 20 players online:");
+        assert_eq!(
+            kinds(tracker.update(&get_server_status(&server).await.unwrap())),
+            [ServerEventKind::PlayerLeft(player("alex"))]
+        );
+    }
+
+    #[test]
+    fn incomplete_lists_are_ignored() {
+        let mut tracker = StatusTracker::new("test".into());
+        tracker.update(&online(&["alex", "steve"], 2));
+
+        // a sample of the players, like the one SLP gives
+        assert!(tracker.update(&online(&["notch"], 3)).is_empty());
+        assert_eq!(
+            kinds(tracker.update(&online(&["alex", "notch", "steve"], 3))),
+            [ServerEventKind::PlayerJoined(player("notch"))]
+        );
+    }
+}
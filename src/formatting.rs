@@ -0,0 +1,526 @@
+//! Minecraft's text formatting, as found in the responses of the server and in nicknames: legacy
+//! formatting codes (`§c`) and the tags of Placeholder API style mods (`<red>`, `<gradient:..>`).
+//! Text is parsed into styled spans, which can be rendered as plain text or as the contents of a
+//! discord `ansi` code block.
+
+use std::fmt::Write;
+
+/// The 16 named colours, along with their legacy codes and the closest colour discord's ANSI code
+/// blocks support.
+const NAMED_COLOURS: [(&str, char, [u8; 3], u8); 16] = [
+    ("black", '0', [0x00, 0x00, 0x00], 30),
+    ("dark_blue", '1', [0x00, 0x00, 0xaa], 34),
+    ("dark_green", '2', [0x00, 0xaa, 0x00], 32),
+    ("dark_aqua", '3', [0x00, 0xaa, 0xaa], 36),
+    ("dark_red", '4', [0xaa, 0x00, 0x00], 31),
+    ("dark_purple", '5', [0xaa, 0x00, 0xaa], 35),
+    ("gold", '6', [0xff, 0xaa, 0x00], 33),
+    ("gray", '7', [0xaa, 0xaa, 0xaa], 37),
+    ("dark_gray", '8', [0x55, 0x55, 0x55], 30),
+    ("blue", '9', [0x55, 0x55, 0xff], 34),
+    ("green", 'a', [0x55, 0xff, 0x55], 32),
+    ("aqua", 'b', [0x55, 0xff, 0xff], 36),
+    ("red", 'c', [0xff, 0x55, 0x55], 31),
+    ("light_purple", 'd', [0xff, 0x55, 0xff], 35),
+    ("yellow", 'e', [0xff, 0xff, 0x55], 33),
+    ("white", 'f', [0xff, 0xff, 0xff], 37),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub colour: Option<[u8; 3]>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// Parsed text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Text {
+    pub spans: Vec<Span>,
+}
+
+/// How the text inside a gradient or rainbow tag is coloured.
+#[derive(Clone)]
+enum Colouring {
+    Gradient(Vec<[u8; 3]>),
+    Rainbow,
+}
+
+/// A tag which hasn't been closed yet.
+struct OpenTag {
+    name: String,
+    /// The style before the tag.
+    previous: Style,
+    /// For gradients, how to colour the spans from `start` on once the tag is closed.
+    colouring: Option<(Colouring, usize)>,
+}
+
+#[derive(Default)]
+struct Parser {
+    spans: Vec<Span>,
+    text: String,
+    style: Style,
+    open: Vec<OpenTag>,
+}
+
+impl Parser {
+    /// Ends the current span, since the style is about to change.
+    fn flush(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.text);
+
+        // the first span inside a gradient can't be merged into the one before it, or the gradient
+        // wouldn't find it when it's closed
+        let starts_colouring = self
+            .open
+            .iter()
+            .any(|tag| matches!(tag.colouring, Some((_, start)) if start == self.spans.len()));
+        if starts_colouring {
+            self.spans.push(Span {
+                text,
+                style: self.style,
+            });
+        } else {
+            push_span(&mut self.spans, text, self.style);
+        }
+    }
+
+    /// Applies a legacy formatting code, ignoring unknown ones.
+    fn code(&mut self, code: char) {
+        let code = code.to_ascii_lowercase();
+        self.flush();
+        if let Some(&(_, _, rgb, _)) = NAMED_COLOURS.iter().find(|colour| colour.1 == code) {
+            // like in the game, colours reset the other formatting
+            self.style = Style {
+                colour: Some(rgb),
+                ..Style::default()
+            };
+            return;
+        }
+
+        match code {
+            'k' => self.style.obfuscated = true,
+            'l' => self.style.bold = true,
+            'm' => self.style.strikethrough = true,
+            'n' => self.style.underlined = true,
+            'o' => self.style.italic = true,
+            'r' => self.style = Style::default(),
+            _ => {}
+        }
+    }
+
+    /// Applies the tag between `<` and `>`. Returns false if it isn't a known tag.
+    fn tag(&mut self, tag: &str) -> bool {
+        if let Some(name) = tag.strip_prefix('/') {
+            return self.close(name);
+        }
+
+        let mut args = tag.split(':');
+        let name = args.next().unwrap_or_default();
+        let args: Vec<&str> = args.collect();
+
+        let mut style = self.style;
+        let mut colouring = None;
+        match (name, &args[..]) {
+            ("reset" | "r", []) => {
+                self.close_all();
+                return true;
+            }
+            ("color" | "colour" | "c", [colour]) => match parse_colour(colour) {
+                Some(rgb) => style.colour = Some(rgb),
+                None => return false,
+            },
+            ("gradient" | "gr", colours) if colours.len() >= 2 => {
+                let colours = colours
+                    .iter()
+                    .map(|colour| parse_colour(colour))
+                    .collect::<Option<_>>();
+                let Some(colours) = colours else {
+                    return false;
+                };
+                colouring = Some(Colouring::Gradient(colours));
+            }
+            ("gradient" | "gr" | "rainbow" | "rb", _) => colouring = Some(Colouring::Rainbow),
+            ("bold" | "b", []) => style.bold = true,
+            ("italic" | "i" | "em", []) => style.italic = true,
+            ("underlined" | "underline" | "u", []) => style.underlined = true,
+            ("strikethrough" | "st", []) => style.strikethrough = true,
+            ("obfuscated" | "obf", []) => style.obfuscated = true,
+            (name, []) => match parse_colour(name) {
+                Some(rgb) => style.colour = Some(rgb),
+                None => return false,
+            },
+            _ => return false,
+        }
+
+        self.flush();
+        self.open.push(OpenTag {
+            name: name.to_owned(),
+            previous: self.style,
+            colouring: colouring.map(|colouring| (colouring, self.spans.len())),
+        });
+        self.style = style;
+        true
+    }
+
+    /// Closes the last tag called `name` (or the last tag, if `name` is empty), along with every
+    /// tag opened after it.
+    fn close(&mut self, name: &str) -> bool {
+        let Some(index) = self
+            .open
+            .iter()
+            .rposition(|tag| name.is_empty() || tag.name == name)
+        else {
+            // strip the closing tags of known tags, even if they weren't opened
+            return name.is_empty() || parse_colour(name).is_some();
+        };
+
+        self.flush();
+        while self.open.len() > index {
+            self.pop();
+        }
+        true
+    }
+
+    fn close_all(&mut self) {
+        self.flush();
+        while !self.open.is_empty() {
+            self.pop();
+        }
+    }
+
+    fn pop(&mut self) {
+        let tag = self.open.pop().unwrap();
+        self.style = tag.previous;
+        if let Some((colouring, start)) = tag.colouring {
+            let spans = self.spans.split_off(start);
+            self.spans.extend(colour(spans, &colouring));
+        }
+    }
+
+    fn finish(mut self) -> Text {
+        self.close_all();
+        Text { spans: self.spans }
+    }
+}
+
+impl Text {
+    pub fn parse(text: &str) -> Text {
+        let mut parser = Parser::default();
+        let mut rest = text;
+
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+
+            if c == '§' {
+                let mut chars = rest.chars();
+                // the hex colours of spigot-like servers: §x§r§r§g§g§b§b
+                if let Some((rgb, length)) = hex_code(rest) {
+                    parser.flush();
+                    parser.style = Style {
+                        colour: Some(rgb),
+                        ..Style::default()
+                    };
+                    rest = &rest[length..];
+                    continue;
+                }
+
+                // unknown codes are dropped as well, since the game doesn't show them
+                if let Some(code) = chars.next() {
+                    parser.code(code);
+                    rest = chars.as_str();
+                }
+                continue;
+            }
+
+            if c == '<' {
+                if let Some(end) = rest
+                    .find(['<', '>'])
+                    .filter(|end| rest[*end..].starts_with('>'))
+                {
+                    if parser.tag(&rest[..end]) {
+                        rest = &rest[end + 1..];
+                        continue;
+                    }
+                }
+            }
+
+            parser.text.push(c);
+        }
+
+        parser.finish()
+    }
+
+    /// Returns the text without any formatting.
+    pub fn plain(&self) -> String {
+        self.spans.iter().map(|span| &*span.text).collect()
+    }
+
+    /// Whether any part of the text has a colour, or is bold or underlined, which are the styles
+    /// [`Text::ansi`] can show.
+    pub fn is_styled(&self) -> bool {
+        self.spans
+            .iter()
+            .any(|span| span.style.colour.is_some() || span.style.bold || span.style.underlined)
+    }
+
+    /// Renders the text as the contents of a discord ```` ```ansi ```` code block. Colours are
+    /// replaced with the closest of the 8 that discord supports, and only bold and underlined
+    /// text is shown as such.
+    pub fn ansi(&self) -> String {
+        let mut result = String::new();
+        let mut current = String::from("0");
+
+        for span in &self.spans {
+            let mut codes = String::from("0");
+            if span.style.bold {
+                codes += ";1";
+            }
+            if span.style.underlined {
+                codes += ";4";
+            }
+            if let Some(colour) = span.style.colour {
+                write!(&mut codes, ";{}", ansi_colour(colour)).unwrap();
+            }
+
+            if codes != current {
+                write!(&mut result, "\x1b[{codes}m").unwrap();
+                current = codes;
+            }
+            // a code block can't contain the end of a code block
+            result += &span.text.replace("```", "``\u{200b}`");
+        }
+
+        result
+    }
+}
+
+/// Appends a span, merging it into the last one if they look the same.
+fn push_span(spans: &mut Vec<Span>, text: String, style: Style) {
+    match spans.last_mut() {
+        Some(last) if last.style == style => last.text += &text,
+        _ => spans.push(Span { text, style }),
+    }
+}
+
+/// Parses a colour name or a `#rrggbb` hex colour.
+fn parse_colour(colour: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = colour.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some([channel(0)?, channel(2)?, channel(4)?]);
+    }
+
+    NAMED_COLOURS
+        .iter()
+        .find(|named| named.0 == colour)
+        .map(|named| named.2)
+}
+
+/// Parses the `x§r§r§g§g§b§b` part of a spigot hex colour, returning the colour and the length of
+/// the code in bytes.
+fn hex_code(code: &str) -> Option<([u8; 3], usize)> {
+    let mut chars = code.chars();
+    if !matches!(chars.next(), Some('x' | 'X')) {
+        return None;
+    }
+
+    let mut hex = String::from("#");
+    for _ in 0..6 {
+        if chars.next() != Some('§') {
+            return None;
+        }
+        hex.push(chars.next()?);
+    }
+    Some((
+        parse_colour(&hex.to_ascii_lowercase())?,
+        code.len() - chars.as_str().len(),
+    ))
+}
+
+/// Colours each character of `spans`, keeping the rest of their style.
+fn colour(spans: Vec<Span>, colouring: &Colouring) -> Vec<Span> {
+    let length = spans
+        .iter()
+        .map(|span| span.text.chars().count())
+        .sum::<usize>();
+    let mut result = vec![];
+    let mut i = 0;
+
+    for span in spans {
+        for c in span.text.chars() {
+            let t = if length > 1 {
+                i as f32 / (length - 1) as f32
+            } else {
+                0.0
+            };
+            let rgb = match colouring {
+                Colouring::Gradient(colours) => gradient(colours, t),
+                Colouring::Rainbow => rainbow(i as f32 / length as f32),
+            };
+            let style = Style {
+                colour: Some(rgb),
+                ..span.style
+            };
+            push_span(&mut result, c.to_string(), style);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+/// Returns the colour at `t` (between 0 and 1) of a gradient through `colours`.
+fn gradient(colours: &[[u8; 3]], t: f32) -> [u8; 3] {
+    let position = t * (colours.len() - 1) as f32;
+    let index = (position as usize).min(colours.len() - 2);
+    let t = position - index as f32;
+    let (from, to) = (colours[index], colours[index + 1]);
+
+    std::array::from_fn(|channel| {
+        (from[channel] as f32 + (to[channel] as f32 - from[channel] as f32) * t).round() as u8
+    })
+}
+
+/// Returns the colour at `t` (between 0 and 1) of the rainbow, i.e. the hue `t * 360°`.
+fn rainbow(t: f32) -> [u8; 3] {
+    let hue = t * 6.0;
+    let x = ((1.0 - (hue % 2.0 - 1.0).abs()) * 255.0).round() as u8;
+    match hue as u8 {
+        0 => [255, x, 0],
+        1 => [x, 255, 0],
+        2 => [0, 255, x],
+        3 => [0, x, 255],
+        4 => [x, 0, 255],
+        _ => [255, 0, x],
+    }
+}
+
+/// Returns the ANSI code of the colour closest to `rgb` among the ones discord supports.
+fn ansi_colour(rgb: [u8; 3]) -> u8 {
+    let distance = |other: [u8; 3]| {
+        (0..3)
+            .map(|i| (rgb[i] as i32 - other[i] as i32).pow(2))
+            .sum::<i32>()
+    };
+
+    NAMED_COLOURS
+        .iter()
+        .min_by_key(|named| distance(named.2))
+        .unwrap()
+        .3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styles(text: &Text) -> Vec<(&str, Option<[u8; 3]>, bool)> {
+        text.spans
+            .iter()
+            .map(|span| (&*span.text, span.style.colour, span.style.bold))
+            .collect()
+    }
+
+    const RED: Option<[u8; 3]> = Some([0xff, 0x55, 0x55]);
+    const GREEN: Option<[u8; 3]> = Some([0x55, 0xff, 0x55]);
+
+    #[test]
+    fn legacy_codes() {
+        let text = Text::parse("§aThere are §l2§r§a players §zonline");
+        assert_eq!(text.plain(), "There are 2 players online");
+        assert_eq!(
+            styles(&text),
+            [
+                ("There are ", GREEN, false),
+                ("2", GREEN, true),
+                (" players online", GREEN, false),
+            ]
+        );
+
+        let text = Text::parse("§x§f§f§0§0§0§0red§r and §cred");
+        assert_eq!(text.spans[0].style.colour, Some([0xff, 0, 0]));
+        assert_eq!(text.plain(), "red and red");
+        assert_eq!(Text::parse("trailing §").plain(), "trailing ");
+    }
+
+    #[test]
+    fn tags() {
+        let text = Text::parse("<red>a<b>b</red>c<#00ff00>d</>e");
+        assert_eq!(
+            styles(&text),
+            [
+                ("a", RED, false),
+                ("b", RED, true),
+                ("c", None, false),
+                ("d", Some([0, 0xff, 0]), false),
+                ("e", None, false),
+            ]
+        );
+
+        // anything that isn't a known tag is kept as it is
+        assert_eq!(
+            Text::parse("<alex> i <3 <you>").plain(),
+            "<alex> i <3 <you>"
+        );
+        assert_eq!(Text::parse("<c:gold>x</c></gold>y<reset>").plain(), "xy");
+    }
+
+    #[test]
+    fn gradients() {
+        let text = Text::parse("<gradient:#000000:#ffffff>abc</gradient>d");
+        let colours: Vec<_> = text.spans.iter().map(|span| span.style.colour).collect();
+        assert_eq!(
+            colours,
+            [
+                Some([0, 0, 0]),
+                Some([128, 128, 128]),
+                Some([255, 255, 255]),
+                None
+            ]
+        );
+        assert_eq!(text.plain(), "abcd");
+
+        // with unstyled text before it, which looks the same as the start of the gradient
+        let text = Text::parse("x<gradient:#ff0000:#0000ff>abc</gradient>");
+        let colours: Vec<_> = text.spans.iter().map(|span| span.style.colour).collect();
+        assert_eq!(
+            colours,
+            [
+                None,
+                Some([255, 0, 0]),
+                Some([128, 0, 128]),
+                Some([0, 0, 255])
+            ]
+        );
+        assert_eq!(text.plain(), "xabc");
+
+        let text = Text::parse("<rainbow>unclosed rainbow");
+        assert!(text.spans.len() > 1);
+        assert_eq!(text.spans[0].style.colour, Some([255, 0, 0]));
+    }
+
+    #[test]
+    fn ansi() {
+        let text = Text::parse("§cred §lbold§r plain ```");
+        assert_eq!(
+            text.ansi(),
+            "\x1b[0;31mred \x1b[0;1;31mbold\x1b[0m plain ``\u{200b}`"
+        );
+        assert!(text.is_styled());
+        assert!(!Text::parse("plain").is_styled());
+    }
+}
@@ -1,6 +1,41 @@
-use rcon::Result;
+//! A client for the RCON protocol. See <https://wiki.vg/RCON>.
 
-type Connection = rcon::Connection<tokio::net::TcpStream>;
+use std::io::{self, ErrorKind};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::Duration;
+
+const AUTH: i32 = 3;
+const AUTH_RESPONSE: i32 = 2;
+const EXEC_COMMAND: i32 = 2;
+
+/// The id the server answers failed logins with.
+const AUTH_FAILED: i32 = -1;
+
+/// Longer commands don't work reliably.
+pub const MAX_COMMAND_LENGTH: usize = 1413;
+
+/// The server splits responses into packets of 4096 bytes, so this is plenty.
+const MAX_PACKET_LENGTH: i32 = 1 << 16;
+
+/// Sending packets in quick succession can crash the server, see
+/// <https://bugs.mojang.com/browse/MC-72390>.
+const PACKET_DELAY: Duration = Duration::from_millis(3);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("authentication failed")]
+    Auth,
+
+    #[error("command too long")]
+    CommandTooLong,
+
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
 
 pub struct Interface {
     address: Box<str>,
@@ -17,11 +52,16 @@ impl Interface {
         }
     }
 
+    /// Returns true iff this interface connects to `addr` using `pass`.
+    pub fn has_credentials(&self, addr: &str, pass: &str) -> bool {
+        *self.address == *addr && *self.password == *pass
+    }
+
     pub async fn exec(&mut self, command: &str) -> Result<String> {
         if let Some(conn) = &mut self.connection {
             match conn.cmd(command).await {
-                x @ Ok(..) | x @ Err(rcon::Error::CommandTooLong | rcon::Error::Auth) => return x,
-                Err(rcon::Error::Io(..)) => {} // purposefully exhaustive for future-proofness
+                x @ Ok(..) | x @ Err(Error::CommandTooLong | Error::Auth) => return x,
+                Err(Error::Io(..)) => {} // purposefully exhaustive for future-proofness
             }
         }
 
@@ -31,9 +71,170 @@ impl Interface {
 
     /// Both updates the internal connection and also returns it.
     async fn renew_connection(&mut self) -> Result<&mut Connection> {
-        match Connection::connect(&*self.address, &self.password).await {
+        self.connection = None;
+        match Connection::connect(&self.address, &self.password).await {
             Ok(conn) => Ok(self.connection.insert(conn)),
             Err(why) => Err(why),
         }
     }
 }
+
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: Vec<u8>,
+}
+
+struct Connection {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Connection {
+    async fn connect(address: &str, password: &str) -> Result<Self> {
+        let mut conn = Self {
+            stream: TcpStream::connect(address).await?,
+            next_id: 1,
+        };
+
+        conn.send(AUTH, password.as_bytes()).await?;
+        loop {
+            let packet = conn.receive().await?;
+            if packet.kind == AUTH_RESPONSE {
+                return match packet.id {
+                    AUTH_FAILED => Err(Error::Auth),
+                    _ => Ok(conn),
+                };
+            }
+        }
+    }
+
+    async fn cmd(&mut self, command: &str) -> Result<String> {
+        if command.len() > MAX_COMMAND_LENGTH {
+            return Err(Error::CommandTooLong);
+        }
+
+        // long responses are split into several packets, without any way to tell which one is the
+        // last. the server handles commands in order though, so once the response to an empty
+        // command arrives, the real one is complete
+        let id = self.send(EXEC_COMMAND, command.as_bytes()).await?;
+        tokio::time::sleep(PACKET_DELAY).await;
+        let end_id = self.send(EXEC_COMMAND, b"").await?;
+
+        let mut response = vec![];
+        loop {
+            let packet = self.receive().await?;
+            if packet.id == end_id {
+                break;
+            }
+            if packet.id == id {
+                response.extend_from_slice(&packet.body);
+            }
+        }
+
+        // the response is split by bytes, so only the whole of it is valid UTF-8
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    }
+
+    /// Sends a packet and returns its id.
+    async fn send(&mut self, kind: i32, body: &[u8]) -> io::Result<i32> {
+        let id = self.next_id;
+        // negative ids are used to signal failed logins
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+
+        // write everything at once, so that it's sent as a single TCP packet
+        self.stream.write_all(&encode(id, kind, body)).await?;
+
+        Ok(id)
+    }
+
+    async fn receive(&mut self) -> io::Result<Packet> {
+        let length = self.stream.read_i32_le().await?;
+        if !(10..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "invalid packet length",
+            ));
+        }
+
+        let mut packet = vec![0; length as usize];
+        self.stream.read_exact(&mut packet).await?;
+        let id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+        let kind = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+        // drop the two terminating nulls
+        packet.truncate(packet.len() - 2);
+        packet.drain(..8);
+
+        Ok(Packet {
+            id,
+            kind,
+            body: packet,
+        })
+    }
+}
+
+fn encode(id: i32, kind: i32, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 14);
+    packet.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet.extend_from_slice(&kind.to_le_bytes());
+    packet.extend_from_slice(body);
+    packet.extend_from_slice(&[0, 0]);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rcon::MockRcon;
+
+    #[tokio::test]
+    async fn fragmented_responses() {
+        let server = MockRcon::start("hunter2").await;
+        // make sure a character is split between two packets
+        let response = format!("{}é{}", "a".repeat(4095), "b".repeat(5000));
+        server.respond("list", &*response);
+
+        let mut interface = server.interface();
+        assert_eq!(interface.exec("list").await.unwrap(), response);
+        server.fragment(7);
+        assert_eq!(interface.exec("list").await.unwrap(), response);
+        assert_eq!(server.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn reconnection() {
+        let server = MockRcon::start("hunter2").await;
+        server.respond("seed", "Seed: [42]");
+        server.delay(Duration::from_millis(20));
+
+        let mut interface = server.interface();
+        assert_eq!(interface.exec("seed").await.unwrap(), "Seed: [42]");
+        server.disconnect();
+        assert_eq!(interface.exec("seed").await.unwrap(), "Seed: [42]");
+        assert_eq!(server.connections(), 2);
+        assert_eq!(server.commands(), ["seed", "seed"]);
+
+        let address = server.address().to_owned();
+        drop(server);
+        assert!(matches!(interface.exec("seed").await, Err(Error::Io(..))));
+        assert!(matches!(
+            Interface::new(address, "hunter2").exec("seed").await,
+            Err(Error::Io(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn errors() {
+        let server = MockRcon::start("hunter2").await;
+
+        let mut interface = Interface::new(server.address(), "password");
+        assert!(matches!(interface.exec("list").await, Err(Error::Auth)));
+        let mut interface = server.interface();
+        assert!(matches!(
+            interface.exec(&"a".repeat(2000)).await,
+            Err(Error::CommandTooLong)
+        ));
+        assert!(interface.exec("foo").await.unwrap().starts_with("Unknown"));
+    }
+}
@@ -0,0 +1,313 @@
+//! Renders the self-updating list message, either as an embed or as plain text.
+
+use std::fmt::Write;
+
+use poise::serenity_prelude::{
+    Colour, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, Timestamp,
+};
+use uuid_mc::PlayerUuid;
+
+use crate::config::ListConfig;
+use crate::formatting::Text;
+use crate::server_status::OnlineServerStatus;
+
+const ONLINE_COLOUR: Colour = Colour::new(0x57f287);
+const LAGGING_COLOUR: Colour = Colour::new(0xfee75c);
+const OFFLINE_COLOUR: Colour = Colour::new(0xed4245);
+
+/// The windows of the TPS values reported by the server, in order.
+const TPS_WINDOWS: [&str; 5] = ["5s", "10s", "1m", "5m", "15m"];
+
+/// A message can have at most 10 embeds, one of which is the status.
+const MAX_HEADS: usize = 9;
+
+/// The maximum length of an embed field's value.
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// A player as shown in the list.
+pub struct ListedPlayer<'a> {
+    pub name: &'a str,
+    /// The nickname (or its replacement, if it was naughty).
+    pub nickname: Option<Text>,
+    pub uuid: Option<PlayerUuid>,
+}
+
+/// What the list message shows.
+pub enum ListContent<'a> {
+    Offline,
+    /// The status couldn't be determined.
+    Error(&'a str),
+    Online {
+        status: &'a OnlineServerStatus,
+        players: Vec<ListedPlayer<'a>>,
+    },
+}
+
+impl ListContent<'_> {
+    /// Renders the content as plain text, the way it was shown before embeds.
+    pub fn text(&self, timestamp: u64) -> String {
+        let mut text = match self {
+            ListContent::Offline => "The server is offline.".to_owned(),
+            ListContent::Error(why) => why.to_string(),
+            ListContent::Online { status, players } => online_text(status, players),
+        };
+
+        write!(&mut text, "\n\nLast update: <t:{timestamp}:T>").unwrap();
+        text
+    }
+
+    /// Renders the content as embeds: one with the status, followed by one per player showing
+    /// their head if `config.head_url` is set and there are few enough players.
+    pub fn embeds(&self, server_name: &str, config: &ListConfig) -> Vec<CreateEmbed> {
+        let embed = CreateEmbed::new()
+            .title(server_name)
+            .footer(CreateEmbedFooter::new("Last update"))
+            .timestamp(Timestamp::now());
+
+        let (status, players) = match self {
+            ListContent::Offline => {
+                return vec![embed
+                    .colour(OFFLINE_COLOUR)
+                    .description("The server is offline.")]
+            }
+            ListContent::Error(why) => {
+                return vec![embed.colour(OFFLINE_COLOUR).description(*why)];
+            }
+            ListContent::Online { status, players } => (status, players),
+        };
+
+        let lagging = status.tps.is_some_and(|tps| tps[2] < config.low_tps);
+        let colour = if lagging {
+            LAGGING_COLOUR
+        } else {
+            ONLINE_COLOUR
+        };
+
+        let mut embed = embed.colour(colour).description(format!(
+            "The server is online. There are {}/{} connected players.",
+            status.current_players, status.max_players
+        ));
+
+        let heads = match &config.head_url {
+            Some(template) if !players.is_empty() && players.len() <= MAX_HEADS => players
+                .iter()
+                .map(|player| {
+                    let mut author = CreateEmbedAuthor::new(player.name);
+                    if let Some(url) = head_url(template, player) {
+                        author = author.icon_url(url);
+                    }
+                    let mut head = CreateEmbed::new().colour(colour).author(author);
+                    if let Some(nickname) = &player.nickname {
+                        head = head.description(nickname.plain());
+                    }
+                    head
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        // the heads already show the players, along with their nicknames
+        if heads.is_empty() && !players.is_empty() {
+            let names = players.iter().map(|player| player.name.to_owned());
+            embed = embed.field("Players", field_lines(names), true);
+
+            if players.iter().any(|player| player.nickname.is_some()) {
+                let nicknames = players.iter().map(|player| match &player.nickname {
+                    Some(nickname) => nickname.plain(),
+                    None => "-".into(),
+                });
+                embed = embed.field("Nicknames", field_lines(nicknames), true);
+            }
+        }
+
+        if let Some(tps) = status.tps {
+            for (window, tps) in TPS_WINDOWS.iter().zip(tps) {
+                embed = embed.field(format!("TPS ({window})"), format!("{tps:.2}"), true);
+            }
+        }
+
+        if let Some(version) = &status.version {
+            let mut value = version.clone();
+            if let Some(protocol) = status.protocol {
+                write!(&mut value, " (protocol {protocol})").unwrap();
+            }
+            embed = embed.field("Version", value, true);
+        }
+
+        if let Some(map) = &status.map {
+            let mut value = map.clone();
+            if let Some(game_type) = &status.game_type {
+                write!(&mut value, " ({game_type})").unwrap();
+            }
+            embed = embed.field("Map", value, true);
+        }
+
+        if let Some(plugins) = status.plugins.as_ref().filter(|p| !p.is_empty()) {
+            embed = embed.field("Plugins", truncate(&plugins.join(", ")), false);
+        }
+
+        let motd = status.motd.as_deref().map(|motd| Text::parse(motd).plain());
+        if let Some(motd) = motd.filter(|motd| !motd.trim().is_empty()) {
+            embed = embed.field("MOTD", truncate(&motd), false);
+        }
+
+        let mut embeds = vec![embed];
+        embeds.extend(heads);
+        embeds
+    }
+}
+
+fn online_text(status: &OnlineServerStatus, players: &[ListedPlayer]) -> String {
+    let mut text = format!(
+        "The server is online. There are {}/{} connected players",
+        status.current_players, status.max_players
+    );
+
+    // the list can be empty even if there are players online, since some status sources only
+    // report a sample of the players
+    if !players.is_empty() {
+        let styled = players
+            .iter()
+            .any(|player| player.nickname.as_ref().is_some_and(Text::is_styled));
+        let players: Vec<String> = players
+            .iter()
+            .map(|player| match &player.nickname {
+                Some(nickname) if styled => format!("{} ({}\x1b[0m)", player.name, nickname.ansi()),
+                Some(nickname) => format!("{} ({})", player.name, nickname.plain()),
+                None => player.name.to_owned(),
+            })
+            .collect();
+        let language = if styled { "ansi" } else { "" };
+        write!(&mut text, ": ```{language}\n{}```", players.join("\n")).unwrap();
+    } else {
+        writeln!(&mut text, ".").unwrap();
+    }
+
+    if let Some(version) = &status.version {
+        write!(&mut text, "\nVersion: {version}").unwrap();
+        if let Some(protocol) = status.protocol {
+            write!(&mut text, " (protocol {protocol})").unwrap();
+        }
+    }
+
+    if let Some(map) = &status.map {
+        write!(&mut text, "\nMap: {map}").unwrap();
+        if let Some(game_type) = &status.game_type {
+            write!(&mut text, " ({game_type})").unwrap();
+        }
+    }
+
+    if let Some(plugins) = status.plugins.as_ref().filter(|p| !p.is_empty()) {
+        write!(&mut text, "\nPlugins: {}", plugins.join(", ")).unwrap();
+    }
+
+    if let Some(motd) = &status.motd {
+        let motd = Text::parse(motd);
+        if motd.is_styled() {
+            write!(&mut text, "\nMOTD: ```ansi\n{}```", motd.ansi()).unwrap();
+        } else {
+            write!(&mut text, "\nMOTD: ```\n{}```", motd.plain()).unwrap();
+        }
+    }
+
+    if let Some(tps) = status.tps {
+        write!(
+            &mut text,
+            "\nTPS info: ```\n5s    10s   1m    5m    15m  \n{:>5.2} {:>5.2} {:>5.2} {:>5.2} {:>5.2}```",
+            tps[0], tps[1], tps[2], tps[3], tps[4]
+        )
+        .unwrap();
+    }
+
+    text
+}
+
+/// Fills in the head URL template. Returns None if it needs the player's UUID, but it's unknown.
+fn head_url(template: &str, player: &ListedPlayer) -> Option<String> {
+    let mut url = template.replace("{name}", player.name);
+    if url.contains("{uuid}") {
+        let uuid = player.uuid?.as_uuid().simple().to_string();
+        url = url.replace("{uuid}", &uuid);
+    }
+    Some(url)
+}
+
+/// Joins `lines` into a field value, leaving out the lines that don't fit.
+fn field_lines(lines: impl ExactSizeIterator<Item = String>) -> String {
+    let total = lines.len();
+    let mut value = String::new();
+
+    for (i, line) in lines.enumerate() {
+        // leave room for the "and N more" line
+        if value.len() + line.len() + 1 > MAX_FIELD_LENGTH - 20 {
+            write!(&mut value, "... and {} more", total - i).unwrap();
+            break;
+        }
+        value.push_str(&line);
+        value.push('\n');
+    }
+
+    value
+}
+
+fn truncate(value: &str) -> String {
+    if value.len() <= MAX_FIELD_LENGTH {
+        return value.to_owned();
+    }
+
+    let mut end = MAX_FIELD_LENGTH - 3;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &value[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(name: &str) -> ListedPlayer<'_> {
+        ListedPlayer {
+            name,
+            nickname: None,
+            uuid: None,
+        }
+    }
+
+    #[test]
+    fn head_urls() {
+        let mut steve = player("Steve");
+        assert_eq!(
+            head_url("https://mc-heads.net/avatar/{name}", &steve).as_deref(),
+            Some("https://mc-heads.net/avatar/Steve")
+        );
+        assert_eq!(
+            head_url("https://crafatar.com/avatars/{uuid}", &steve),
+            None
+        );
+
+        steve.uuid = Some(
+            PlayerUuid::new_with_uuid(
+                uuid_mc::Uuid::parse_str("8667ba71-b85a-4004-af54-457a9734eed7").unwrap(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            head_url("https://crafatar.com/avatars/{uuid}", &steve).as_deref(),
+            Some("https://crafatar.com/avatars/8667ba71b85a4004af54457a9734eed7")
+        );
+    }
+
+    #[test]
+    fn long_fields_are_cut_off() {
+        let lines = (0..200).map(|i| format!("player_{i:03}"));
+        let value = field_lines(lines);
+
+        assert!(value.len() <= MAX_FIELD_LENGTH);
+        assert!(value.starts_with("player_000\n"));
+        assert!(value.ends_with("more"));
+
+        let value = truncate(&"é".repeat(MAX_FIELD_LENGTH));
+        assert!(value.len() <= MAX_FIELD_LENGTH);
+    }
+}
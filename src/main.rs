@@ -1,39 +1,85 @@
+mod alerts;
+mod announcements;
+mod audit;
+mod chart;
+mod chat_bridge;
 mod commands;
+mod config;
 mod database_api;
-mod env;
+mod events;
+mod formatting;
 mod interface;
+mod list_message;
+#[cfg(test)]
+mod mock_rcon;
+mod moderation;
+mod perf;
+mod permissions;
+mod playtime;
+mod reload;
+mod restarts;
+mod run_policy;
+mod server;
+mod server_log;
 mod server_status;
+mod settings;
+mod store;
+mod supervisor;
 
-use std::fmt::Write;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 
-use database_api::MonadApi;
-use once_cell::sync::OnceCell;
+use once_cell::sync::Lazy;
 use poise::serenity_prelude::{self as serenity, ClientBuilder, CreateMessage, EditMessage};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serenity::{ChannelId, MessageId, RoleId};
+use serenity::{ChannelId, MessageId};
 use tokio::io::AsyncWriteExt;
-use tokio::sync::Mutex;
-
-use crate::server_status::{OnlineServerStatus, ServerStatus};
+use tokio::sync::{broadcast, Mutex};
+
+use crate::alerts::AlertTracker;
+use crate::config::ListStyle;
+use crate::events::{ServerEvent, StatusTracker};
+use crate::formatting::Text;
+use crate::list_message::{ListContent, ListedPlayer};
+use crate::moderation::{Moderator, Verdict};
+use crate::server::Server;
+use crate::server_log::LogEvent;
+use crate::server_status::ServerStatus;
+use crate::settings::Settings;
+use crate::store::Store;
 
 const CACHE_FILE_NAME: &str = "ferrisquery_cache.toml";
 
+/// The JSON error code discord uses for "Missing Permissions".
+const MISSING_PERMISSIONS: isize = 50013;
+
+/// How long the list message stays text after the bot turned out not to be allowed to send embeds,
+/// before embeds are tried again in case the permission was granted since.
+const EMBED_RETRY_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(10 * 60);
+
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
 #[derive(Clone)]
 pub struct Data {
-    interface: Arc<Mutex<interface::Interface>>,
-    op_role_id: RoleId,
-    list_channel_id: ChannelId,
-    cache: Arc<Mutex<Option<Cache>>>,
-    restart_scheduled: Arc<Mutex<bool>>,
-    has_list_json: bool,
-    has_easyauth: bool,
-    server_directory: Box<str>,
-    db_api: Option<Arc<MonadApi>>,
+    settings: Arc<RwLock<Arc<Settings>>>,
+    cache: Arc<Mutex<HashMap<Box<str>, Cache>>>,
+    /// Player and server events from every server. Use `events.subscribe()` to receive them.
+    events: broadcast::Sender<ServerEvent>,
+    /// Events parsed from the log of every server. Use `log_events.subscribe()` to receive them.
+    log_events: broadcast::Sender<LogEvent>,
+    store: Arc<Store>,
+}
+
+impl Data {
+    /// Returns the current settings. These may be replaced at any moment by a config reload, so
+    /// hold on to the returned value for the duration of an operation instead of calling this
+    /// repeatedly.
+    fn settings(&self) -> Arc<Settings> {
+        Arc::clone(&self.settings.read().unwrap())
+    }
 }
 
 async fn on_error<U>(
@@ -51,149 +97,252 @@ async fn on_error<U>(
     }
 }
 
-async fn list_updater(data: Data, http: Arc<poise::serenity_prelude::Http>) {
+async fn list_updater(data: Data, server_name: Box<str>, http: Arc<poise::serenity_prelude::Http>) {
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5));
+    let mut tracker = StatusTracker::new(Arc::from(&*server_name));
+    let mut embeds_denied = None;
+    let mut previous_settings = data.settings();
+    let mut recorder = perf::Recorder::default();
+    let mut alert_tracker = AlertTracker::default();
+    let mut moderator = Moderator::default();
     loop {
         interval.tick().await;
 
-        let status =
-            server_status::get_server_status(&mut *data.interface.lock().await, data.has_list_json)
+        let settings = data.settings();
+        let Some(server) = settings.server(&server_name) else {
+            // the server was removed from the config
+            return;
+        };
+        // the permission may have been granted along with a config change, so try embeds again
+        if !Arc::ptr_eq(&settings, &previous_settings) {
+            embeds_denied = None;
+            previous_settings = Arc::clone(&settings);
+        }
+
+        let status = server_status::get_server_status(server).await;
+
+        if let Ok(status) = &status {
+            for event in tracker.update(status) {
+                // this only fails if there are no subscribers, which is fine
+                let _ = data.events.send(event);
+            }
+        }
+
+        let status = match status {
+            Ok(status) => status,
+            Err(why) => {
+                set_list_message(
+                    &data,
+                    server,
+                    &http,
+                    &ListContent::Error(&why),
+                    &mut embeds_denied,
+                )
                 .await;
+                continue;
+            }
+        };
 
-        match status {
-            Ok(status) => {
-                let ServerStatus::Online(OnlineServerStatus {
-                    current_players,
-                    max_players,
-                    list,
-                    tps,
-                }) = status
-                else {
-                    set_list_text(&data, &http, "The server is offline.").await;
-
-                    // also clear any scheduled restarts
-                    *data.restart_scheduled.lock().await = false;
-                    continue;
-                };
+        let ServerStatus::Online(status) = status else {
+            set_list_message(
+                &data,
+                server,
+                &http,
+                &ListContent::Offline,
+                &mut embeds_denied,
+            )
+            .await;
+
+            // a restart for when nobody is online has happened (or isn't needed anymore)
+            clear_restart_when_empty(&data, server).await;
+            continue;
+        };
 
-                let regex = TAG_REGEX.get().unwrap();
-                let naughty_regex = NAUGHTY_REGEX.get().unwrap();
-                let mut naughty = Vec::new();
-                let players = list
-                    .iter()
-                    .map(|data| {
-                        if let Some(nick) = &data.nickname {
-                            let nick = regex.replace_all(nick, "");
-                            if naughty_regex.is_match(&nick) {
-                                naughty.push(&data.name);
-                                format!("{} ({})", data.name, NAUGHTY_NICKNAME)
-                            } else {
-                                format!("{} ({})", data.name, nick)
-                            }
-                        } else {
-                            data.name.to_string()
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                let mut text = format!(
-                        "The server is online. There are {current_players}/{max_players} connected players"
-                    );
-
-                if current_players > 0 {
-                    write!(&mut text, ": ```\n{}```", players.join("\n")).unwrap();
-                } else {
-                    writeln!(&mut text, ".").unwrap();
-                }
+        recorder.record(&data.store, &server.name, &status).await;
 
-                if let Some(tps) = tps {
-                    write!(&mut text, "\nTPS info: ```\n5s    10s   1m    5m    15m  \n{:>5.2} {:>5.2} {:>5.2} {:>5.2} {:>5.2}```", tps[0], tps[1], tps[2], tps[3], tps[4]).unwrap();
-                }
-                set_list_text(&data, &http, &text).await;
-
-                {
-                    let mut interface = data.interface.lock().await;
-                    for name in naughty {
-                        let _ = interface
-                            .exec(&format!("styled-nicknames set {name} {NAUGHTY_NICKNAME}"))
-                            .await;
-                        let _ = interface.exec(&format!("kick {name} nice try")).await;
-                    }
+        if let (Some(config), Some(tps)) = (&settings.alerts, &status.tps) {
+            let alerts = alert_tracker.update(config, tps, Instant::now());
+            if !alerts.is_empty() {
+                let _http = Arc::clone(&http);
+                let _settings = Arc::clone(&settings);
+                let _name = server_name.clone();
+                tokio::spawn(async move { alerts::post(&_http, &_settings, &_name, alerts).await });
+            }
+        }
+
+        let moderation = &settings.moderation;
+        let mut verdicts = Vec::new();
+        let players = status
+            .list
+            .iter()
+            .map(|data| {
+                let nickname = data.nickname.as_deref().map(Text::parse);
+                let plain = nickname.as_ref().map(Text::plain);
+                let rule = plain.as_deref().and_then(|nick| moderation.find(nick));
+                if let (Some(plain), Some(rule)) = (plain, rule) {
+                    verdicts.push(Verdict {
+                        player: &data.name,
+                        nickname: plain,
+                        rule,
+                    });
                 }
 
-                // if a restart has been scheduled and there are no players online, do it
-                if current_players == 0 && *data.restart_scheduled.lock().await {
-                    let _ = data.interface.lock().await.exec("stop").await;
+                ListedPlayer {
+                    name: &data.name,
+                    nickname: match rule {
+                        Some(rule) if rule.hides_nickname() => Some(Text::parse(&rule.nickname)),
+                        _ => nickname,
+                    },
+                    uuid: data.uuid,
                 }
-            }
-            Err(why) => {
-                set_list_text(&data, &http, &why).await;
-            }
+            })
+            .collect();
+
+        let content = ListContent::Online {
+            status: &status,
+            players,
+        };
+        set_list_message(&data, server, &http, &content, &mut embeds_denied).await;
+
+        let online = status.list.iter().map(|player| &*player.name);
+        let verdicts = moderator.filter(verdicts, online);
+        moderation::enforce(&http, &data.store, server, moderation, verdicts).await;
+
+        // if a restart has been scheduled and there are no players online, do it
+        if status.current_players == 0 && clear_restart_when_empty(&data, server).await {
+            restarts::restart(server).await;
         }
     }
 }
 
-async fn set_list_text(data: &Data, http: &poise::serenity_prelude::Http, text: &str) {
+/// Clears the restart scheduled for when nobody is online, returning whether there was one.
+async fn clear_restart_when_empty(data: &Data, server: &Server) -> bool {
+    if *server.scheduled_restart.lock().await != Some(restarts::ScheduledRestart::WhenEmpty) {
+        return false;
+    }
+    if let Err(why) = restarts::set(&data.store, server, None).await {
+        eprintln!(
+            "Couldn't save the scheduled restart of {}: {why}",
+            server.name
+        );
+    }
+    true
+}
+
+/// Sends or edits the list message of `server`. If the list style allows it, the message is
+/// rendered as an embed; if the bot turns out not to be allowed to send embeds in the list channel,
+/// `embeds_denied` is set to when that happened, and the text version is used until
+/// [`EMBED_RETRY_INTERVAL`] has passed.
+async fn set_list_message(
+    data: &Data,
+    server: &Server,
+    http: &poise::serenity_prelude::Http,
+    content: &ListContent<'_>,
+    embeds_denied: &mut Option<Instant>,
+) {
+    let settings = data.settings();
     let cache = &mut *data.cache.lock().await;
     let timestamp = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs();
-    let text = format!("{text}\n\nLast update: <t:{timestamp}:T>");
 
-    if let Some(Cache {
-        list_channel_id,
-        list_message_id,
-    }) = *cache
-    {
-        if let Err(why) = list_channel_id
-            .edit_message(http, list_message_id, EditMessage::new().content(text))
-            .await
-        {
-            eprintln!("Couldn't edit list message: {why}")
-        }
-    } else {
-        let message = match data
-            .list_channel_id
-            .send_message(http, CreateMessage::new().content(text))
-            .await
-        {
-            Ok(message) => message,
-            Err(why) => {
-                eprintln!("Couldn't send list message: {why}");
-                return;
-            }
+    let mut use_embeds = settings.list.style == ListStyle::Embed
+        && embeds_denied.is_none_or(|since| since.elapsed() >= EMBED_RETRY_INTERVAL);
+    let cached = cache.get(&server.name).copied();
+
+    let message = loop {
+        let (text, rendered) = if use_embeds {
+            (String::new(), content.embeds(&server.name, &settings.list))
+        } else {
+            (content.text(timestamp), vec![])
         };
 
-        *cache = Some(Cache {
-            list_channel_id: data.list_channel_id,
-            list_message_id: message.id,
-        });
-
-        let mut file = match tokio::fs::OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .open(CACHE_FILE_NAME)
-            .await
+        let result = if let Some(Cache {
+            list_channel_id,
+            list_message_id,
+        }) = cached
         {
-            Ok(file) => file,
+            list_channel_id
+                .edit_message(
+                    http,
+                    list_message_id,
+                    EditMessage::new().content(text).embeds(rendered),
+                )
+                .await
+        } else {
+            server
+                .list_channel_id
+                .send_message(http, CreateMessage::new().content(text).embeds(rendered))
+                .await
+        };
+
+        match result {
+            Ok(message) => {
+                if use_embeds {
+                    *embeds_denied = None;
+                }
+                break message;
+            }
+            Err(why) if use_embeds && is_missing_permissions(&why) => {
+                log::warn!(
+                    "Not allowed to send embeds in the list channel of {}, falling back to text",
+                    server.name
+                );
+                *embeds_denied = Some(Instant::now());
+                use_embeds = false;
+            }
             Err(why) => {
-                eprintln!("Couldn't save cache file: {why}");
+                let action = if cached.is_some() { "edit" } else { "send" };
+                eprintln!("Couldn't {action} list message of {}: {why}", server.name);
                 return;
             }
-        };
+        }
+    };
 
-        file.write_all(toml::to_string_pretty(cache).unwrap().as_bytes())
-            .await
-            .unwrap();
+    if cached.is_some() {
+        return;
     }
+
+    cache.insert(
+        server.name.clone(),
+        Cache {
+            list_channel_id: server.list_channel_id,
+            list_message_id: message.id,
+        },
+    );
+
+    let mut file = match tokio::fs::OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(CACHE_FILE_NAME)
+        .await
+    {
+        Ok(file) => file,
+        Err(why) => {
+            eprintln!("Couldn't save cache file: {why}");
+            return;
+        }
+    };
+
+    file.write_all(toml::to_string_pretty(cache).unwrap().as_bytes())
+        .await
+        .unwrap();
 }
 
-static LIST_REGEX: OnceCell<Regex> = OnceCell::new();
-static TAG_REGEX: OnceCell<Regex> = OnceCell::new();
-static NAUGHTY_REGEX: OnceCell<Regex> = OnceCell::new();
-static NAUGHTY_NICKNAME: &str = "I MADE BOOL SAD";
+/// Whether discord rejected a request because the bot is missing a permission.
+fn is_missing_permissions(error: &serenity::Error) -> bool {
+    matches!(
+        error,
+        serenity::Error::Http(serenity::HttpError::UnsuccessfulRequest(response))
+            if response.error.code == MISSING_PERMISSIONS
+    )
+}
+
+static LIST_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^There are (\d+) of a max of (\d+) players online:(?: ((?:\w+, )*\w+))?$").unwrap()
+});
 
 #[derive(Serialize, Deserialize, Copy, Clone)]
 struct Cache {
@@ -205,66 +354,67 @@ struct Cache {
 async fn main() {
     env_logger::init();
 
-    if env::any_set() {
-        env::assert_env_vars();
-    } else {
-        eprintln!("# Environment Variables Help\n{}", env::gen_help());
-        std::process::exit(1);
+    let (config_path, explicit) = match config::config_path() {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            println!("{}", config::USAGE);
+            return;
+        }
+        Err(why) => {
+            eprintln!("{why}\n{}", config::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let config = match config::load(&config_path, explicit) {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!("{}", config::format_errors(&config_path, &errors));
+            std::process::exit(1);
+        }
+    };
+
+    let token = config.discord.token.clone();
+    let settings = Settings::new(&config, None).await;
+    let store = match Store::open(
+        &config.storage.path,
+        &[
+            playtime::SCHEMA,
+            perf::SCHEMA,
+            moderation::SCHEMA,
+            audit::SCHEMA,
+            restarts::SCHEMA,
+        ],
+    ) {
+        Ok(store) => store,
+        Err(why) => {
+            eprintln!("Couldn't open {}: {why}", config.storage.path);
+            std::process::exit(1);
+        }
+    };
+    if let Err(why) = restarts::load(&store, &settings).await {
+        eprintln!("Couldn't load the scheduled restarts: {why}");
     }
 
-    let addr = env::rcon_addr();
-    let password = env::rcon_pass();
-    let token = env::discord_token();
-    let op_role_id = RoleId::new(env::op_role_id());
-    let list_channel_id = ChannelId::new(env::list_channel_id());
-    let has_list_json = env::has_list_json().is_some();
-    let has_easyauth = env::has_easyauth().is_some();
-    let server_directory = env::server_directory();
-
-    let db_api = || -> Option<MonadApi> {
-        Some(MonadApi::new(
-            &env::db_username()?,
-            &env::db_admin_endpoint()?,
-            &env::db_admin_password()?,
-            &env::db_user_endpoint()?,
-            &env::db_user_password()?,
-        ))
-    }();
-
-    LIST_REGEX
-        .set(
-            Regex::new(r"^There are (\d+) of a max of (\d+) players online:(?: ((?:\w+, )*\w+))?$")
-                .unwrap(),
-        )
-        .unwrap();
-    TAG_REGEX
-        .set(
-            Regex::new(r"</?(?:color|c|yellow|dark_blue|dark_purple|gold|red|aqua|gray|light_purple|white|dark_gray|green|dark_green|blue|dark_aqua|dark_green|black|gradient|gr|rainbow|rb|reset)(?::[^>]*)?>")
-                .unwrap(),
-        )
-        .unwrap();
-    NAUGHTY_REGEX
-        .set(
-            Regex::new(&format!(
-                r"(?u)`{0}`{0}`|h{0}t{0}t{0}p{0}s?{0}:{0}/{0}/|d{0}i{0}s{0}c{0}o{0}r{0}d{0}\.{0}g{0}g",
-                r"[^\w!-_a-~]*"
-            ))
-            .unwrap(),
-        )
-        .unwrap();
-
-    let mut cache: Option<Cache> = std::fs::read_to_string(CACHE_FILE_NAME)
+    let mut cache: HashMap<Box<str>, Cache> = std::fs::read_to_string(CACHE_FILE_NAME)
         .ok()
-        .and_then(|string| toml::from_str(&string).ok());
-
-    if let Some(Cache {
-        list_channel_id: cached_channel_id,
-        ..
-    }) = cache
+        .and_then(|string| toml::from_str(&string).ok())
+        .unwrap_or_default();
+
+    // forget the list messages of servers that were removed or moved to a different channel
+    cache.retain(|name, cached| {
+        settings
+            .server(name)
+            .is_some_and(|server| server.list_channel_id == cached.list_channel_id)
+    });
+
+    // reading the messages of chat channels needs a privileged intent, so only ask for it if needed
+    let mut intents = poise::serenity_prelude::GatewayIntents::non_privileged();
+    if settings
+        .servers
+        .iter()
+        .any(|server| server.chat_channel_id.is_some())
     {
-        if cached_channel_id != list_channel_id {
-            cache = None;
-        }
+        intents |= poise::serenity_prelude::GatewayIntents::MESSAGE_CONTENT;
     }
 
     let framework = poise::Framework::builder()
@@ -272,11 +422,26 @@ async fn main() {
             commands: vec![
                 commands::source(),
                 commands::schedule_restart(),
+                commands::server(),
                 commands::run(),
+                commands::audit(),
                 commands::crash(),
+                commands::moderation(),
+                commands::perf(),
+                commands::permissions(),
+                commands::playtime(),
                 commands::user_db(),
                 commands::whitelist(),
             ],
+            command_check: Some(|ctx| Box::pin(commands::check_permissions(ctx))),
+            event_handler: |ctx, event, _framework, data| {
+                Box::pin(async move {
+                    if let poise::serenity_prelude::FullEvent::Message { new_message } = event {
+                        chat_bridge::relay_to_minecraft(ctx, data, new_message).await;
+                    }
+                    Ok(())
+                })
+            },
             on_error: |error| {
                 Box::pin(async move {
                     if let Err(e) = on_error(error).await {
@@ -291,33 +456,48 @@ async fn main() {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
 
                 let data = Data {
-                    interface: Arc::new(Mutex::new(interface::Interface::new(addr, password))),
-                    op_role_id,
-                    list_channel_id,
+                    settings: Arc::new(RwLock::new(Arc::new(settings))),
                     cache: Arc::new(Mutex::new(cache)),
-                    restart_scheduled: Arc::new(Mutex::new(false)),
-                    has_list_json,
-                    has_easyauth,
-                    server_directory: server_directory.into_boxed_str(),
-                    db_api: db_api.map(Arc::new),
+                    events: broadcast::channel(events::CHANNEL_CAPACITY).0,
+                    log_events: broadcast::channel(server_log::CHANNEL_CAPACITY).0,
+                    store: Arc::new(store),
                 };
 
+                // these subscribe before the list updaters start, so they get the initial events
+                let _data = data.clone();
+                let _events = data.events.subscribe();
+                tokio::spawn(async move { playtime::track(_data, _events).await });
+
+                let _data = data.clone();
+                let _http = Arc::clone(&ctx.http);
+                let _events = data.events.subscribe();
+                tokio::spawn(async move { events::post_events(_data, _http, _events).await });
+
                 let _data = data.clone();
                 let _http = Arc::clone(&ctx.http);
+                tokio::spawn(async move { chat_bridge::relay_to_discord(_data, _http).await });
 
-                tokio::spawn(async move { list_updater(_data, _http).await });
+                let _data = data.clone();
+                let _http = Arc::clone(&ctx.http);
+                tokio::spawn(async move { announcements::announce(_data, _http).await });
+
+                let mut updaters = reload::ListUpdaters::default();
+                updaters.sync(&data, &ctx.http, &data.settings()).await;
+
+                let _data = data.clone();
+                let _http = Arc::clone(&ctx.http);
+                tokio::spawn(async move {
+                    reload::watch(_data, _http, config_path, explicit, config, updaters).await
+                });
 
                 Ok(data)
             })
         })
         .build();
 
-    let client = ClientBuilder::new(
-        token,
-        poise::serenity_prelude::GatewayIntents::non_privileged(),
-    )
-    .framework(framework)
-    .await;
+    let client = ClientBuilder::new(token, intents)
+        .framework(framework)
+        .await;
 
     client.unwrap().start().await.unwrap();
 }
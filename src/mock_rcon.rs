@@ -0,0 +1,203 @@
+//! An in-process fake of a minecraft server's RCON interface, for testing.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use poise::serenity_prelude::ChannelId;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::config::StatusSource;
+use crate::interface::Interface;
+use crate::server::Server;
+
+const AUTH: i32 = 3;
+const AUTH_RESPONSE: i32 = 2;
+const RESPONSE_VALUE: i32 = 0;
+
+/// The size of the packets the real server splits long responses into.
+const FRAGMENT_LENGTH: usize = 4096;
+
+#[derive(Default)]
+struct State {
+    password: String,
+    responses: HashMap<String, String>,
+    fragment_length: usize,
+    delay: Duration,
+    disconnect: bool,
+    commands: Vec<String>,
+    connections: usize,
+}
+
+/// A fake server, which stops when dropped.
+pub struct MockRcon {
+    address: String,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockRcon {
+    /// Starts a server which accepts `password`. Commands without a canned response are answered
+    /// like unknown commands.
+    pub async fn start(password: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let state = Arc::new(Mutex::new(State {
+            password: password.into(),
+            fragment_length: FRAGMENT_LENGTH,
+            ..State::default()
+        }));
+
+        let task = tokio::spawn({
+            let state = Arc::clone(&state);
+            async move {
+                // dropping the set (when the task is aborted) closes every connection
+                let mut connections = JoinSet::new();
+                while let Ok((stream, _)) = listener.accept().await {
+                    state.lock().unwrap().connections += 1;
+                    connections.spawn(handle(stream, Arc::clone(&state)));
+                }
+            }
+        });
+
+        Self {
+            address,
+            state,
+            task,
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Returns an interface which logs in with the right password.
+    pub fn interface(&self) -> Interface {
+        Interface::new(&self.address, &self.state.lock().unwrap().password)
+    }
+
+    /// Returns a server which only uses this one as its status source.
+    pub fn server(&self, has_list_json: bool, directory: &str) -> Server {
+        Server {
+            name: "mock".into(),
+            interface: Arc::new(tokio::sync::Mutex::new(self.interface())),
+            list_channel_id: ChannelId::new(1),
+            events_channel_id: None,
+            chat_channel_id: None,
+            scheduled_restart: Default::default(),
+            daily_restarts: Box::new([]),
+            has_list_json,
+            has_easyauth: false,
+            directory: directory.into(),
+            status_sources: Box::new([StatusSource::Rcon]),
+            slp_address: "".into(),
+            query_address: "".into(),
+            announcements: None,
+            supervisor: None,
+        }
+    }
+
+    /// Answers `command` with `response` from now on.
+    pub fn respond(&self, command: &str, response: impl Into<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.responses.insert(command.into(), response.into());
+    }
+
+    /// Splits responses into packets of `length` bytes instead of 4096.
+    pub fn fragment(&self, length: usize) {
+        self.state.lock().unwrap().fragment_length = length;
+    }
+
+    /// Waits for `delay` before answering each command.
+    pub fn delay(&self, delay: Duration) {
+        self.state.lock().unwrap().delay = delay;
+    }
+
+    /// Closes the connection instead of answering the next command.
+    pub fn disconnect(&self) {
+        self.state.lock().unwrap().disconnect = true;
+    }
+
+    /// Returns the commands received so far, in order.
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.clone()
+    }
+
+    /// Returns the number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+}
+
+impl Drop for MockRcon {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    while let Ok((id, kind, body)) = receive(&mut stream).await {
+        let body = String::from_utf8(body).unwrap();
+
+        if kind == AUTH {
+            let id = match body == state.lock().unwrap().password {
+                true => id,
+                false => -1,
+            };
+            send(&mut stream, id, AUTH_RESPONSE, b"").await;
+            continue;
+        }
+
+        // an empty command is what clients use to find the end of a response
+        if body.is_empty() {
+            send(&mut stream, id, RESPONSE_VALUE, b"").await;
+            continue;
+        }
+
+        let (response, fragment_length, delay) = {
+            let mut state = state.lock().unwrap();
+            if std::mem::take(&mut state.disconnect) {
+                return;
+            }
+            state.commands.push(body.clone());
+            let response = match state.responses.get(&body) {
+                Some(response) => response.clone(),
+                None => {
+                    format!("Unknown or incomplete command, see below for error{body}<--[HERE]")
+                }
+            };
+            (response, state.fragment_length, state.delay)
+        };
+
+        tokio::time::sleep(delay).await;
+        if response.is_empty() {
+            send(&mut stream, id, RESPONSE_VALUE, b"").await;
+        }
+        for fragment in response.as_bytes().chunks(fragment_length) {
+            send(&mut stream, id, RESPONSE_VALUE, fragment).await;
+        }
+    }
+}
+
+async fn receive(stream: &mut TcpStream) -> std::io::Result<(i32, i32, Vec<u8>)> {
+    let length = stream.read_i32_le().await?;
+    let id = stream.read_i32_le().await?;
+    let kind = stream.read_i32_le().await?;
+    let mut body = vec![0; length as usize - 8];
+    stream.read_exact(&mut body).await?;
+    body.truncate(body.len() - 2);
+    Ok((id, kind, body))
+}
+
+async fn send(stream: &mut TcpStream, id: i32, kind: i32, body: &[u8]) {
+    let mut packet = vec![];
+    packet.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet.extend_from_slice(&kind.to_le_bytes());
+    packet.extend_from_slice(body);
+    packet.extend_from_slice(&[0, 0]);
+    // the client may have hung up already
+    let _ = stream.write_all(&packet).await;
+}
@@ -0,0 +1,313 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use poise::serenity_prelude::{ChannelId, CreateMessage, Http, MessageBuilder};
+use rusqlite::params;
+
+use crate::config::{ModerationAction, ModerationConfig, ModerationRule};
+use crate::formatting::Text;
+use crate::playtime::now;
+use crate::server::Server;
+use crate::store::Store;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS moderation_log (
+    id INTEGER PRIMARY KEY,
+    time INTEGER NOT NULL,
+    server TEXT NOT NULL,
+    player TEXT NOT NULL,
+    rule TEXT NOT NULL,
+    nickname TEXT NOT NULL,
+    -- one line per action, e.g. \"kick: Kicked alex: nice try\"
+    outcome TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS moderation_log_player ON moderation_log (player COLLATE NOCASE);
+";
+
+/// A player whose nickname broke a rule.
+pub struct Verdict<'a> {
+    pub player: &'a str,
+    /// The offending nickname, without its formatting tags.
+    pub nickname: String,
+    pub rule: &'a ModerationRule,
+}
+
+impl ModerationConfig {
+    /// Returns the first rule `nickname` breaks. The nickname shouldn't have any formatting tags;
+    /// see [`strip_tags`].
+    pub fn find(&self, nickname: &str) -> Option<&ModerationRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(nickname))
+    }
+}
+
+impl ModerationRule {
+    /// Whether the list should show the rule's nickname instead of the offending one.
+    pub fn hides_nickname(&self) -> bool {
+        self.actions
+            .iter()
+            .any(|action| *action != ModerationAction::Notify)
+    }
+
+    pub fn describe_actions(&self) -> String {
+        self.actions.iter().map(|action| action.name()).join(", ")
+    }
+}
+
+/// Removes the formatting tags (e.g. `<red>`) and codes from a nickname.
+pub fn strip_tags(nickname: &str) -> String {
+    Text::parse(nickname).plain()
+}
+
+/// Remembers which verdicts of a single server were already enforced, so that players aren't
+/// punished (or reported) again on every poll while they stay online.
+#[derive(Default)]
+pub struct Moderator {
+    handled: HashSet<(String, String)>,
+}
+
+impl Moderator {
+    /// Returns the verdicts that weren't handled yet, and marks them as handled. `online` is every
+    /// player that is currently online; the verdicts of the others are forgotten.
+    pub fn filter<'a, 'b>(
+        &mut self,
+        verdicts: Vec<Verdict<'a>>,
+        online: impl IntoIterator<Item = &'b str>,
+    ) -> Vec<Verdict<'a>> {
+        let online: HashSet<&str> = online.into_iter().collect();
+        self.handled
+            .retain(|(player, _)| online.contains(&**player));
+
+        verdicts
+            .into_iter()
+            .filter(|verdict| {
+                self.handled
+                    .insert((verdict.player.to_owned(), verdict.nickname.clone()))
+            })
+            .collect()
+    }
+}
+
+/// How much of an entry's outcome is shown in discord.
+const MAX_OUTCOME_LENGTH: usize = 1500;
+
+/// An entry of the moderation log.
+pub struct LogEntry {
+    pub time: i64,
+    pub server: String,
+    pub player: String,
+    pub rule: String,
+    pub nickname: String,
+    pub outcome: String,
+}
+
+/// Carries out the actions of each verdict, and records what happened in the moderation log and
+/// the moderation channel.
+pub async fn enforce(
+    http: &Http,
+    store: &Store,
+    server: &Server,
+    config: &ModerationConfig,
+    verdicts: Vec<Verdict<'_>>,
+) {
+    for verdict in verdicts {
+        let (player, rule) = (verdict.player, verdict.rule);
+        let mut outcome = vec![];
+
+        for action in &rule.actions {
+            let command = match action {
+                ModerationAction::Rename => config
+                    .rename_command
+                    .replace("{name}", player)
+                    .replace("{nickname}", &rule.nickname),
+                ModerationAction::Kick => format!("kick {player} {}", rule.reason),
+                ModerationAction::Ban => format!("ban {player} {}", rule.reason),
+                // posting to the moderation channel is done for every verdict
+                ModerationAction::Notify => continue,
+            };
+
+            let result = match server.interface.lock().await.exec(&command).await {
+                Ok(response) if response.is_empty() => "(no response)".to_owned(),
+                Ok(response) => response,
+                Err(why) => {
+                    log::warn!(
+                        "Couldn't {} {player} on {}: {why}",
+                        action.name(),
+                        server.name
+                    );
+                    format!("failed: {why}")
+                }
+            };
+            outcome.push(format!("{}: {result}", action.name()));
+        }
+
+        let entry = LogEntry {
+            time: now(),
+            server: server.name.to_string(),
+            player: player.to_owned(),
+            rule: rule.name.clone(),
+            nickname: verdict.nickname,
+            outcome: outcome.join("\n"),
+        };
+
+        if let Some(channel_id) = config.channel_id.map(ChannelId::new) {
+            let message = CreateMessage::new().content(describe(&entry));
+            if let Err(why) = channel_id.send_message(http, message).await {
+                eprintln!("Couldn't post to the moderation channel {channel_id}: {why}");
+            }
+        }
+
+        if let Err(why) = record(store, entry).await {
+            log::error!("Couldn't record moderation action: {why}");
+        }
+    }
+}
+
+/// Formats a log entry as a discord message.
+pub fn describe(entry: &LogEntry) -> String {
+    let mut message = MessageBuilder::new();
+    message
+        .push(format!("<t:{}:f> ", entry.time))
+        .push_bold_safe(&entry.player)
+        .push_safe(format!(" on {} broke the rule ", entry.server))
+        .push_mono_safe(&entry.rule)
+        .push(" with the nickname ")
+        .push_mono_safe(&entry.nickname)
+        .push(".");
+    if !entry.outcome.is_empty() {
+        // keep the message well below discord's limit, even if the server's responses are long
+        let mut end = entry.outcome.len().min(MAX_OUTCOME_LENGTH);
+        while !entry.outcome.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.push_codeblock_safe(&entry.outcome[..end], None);
+    }
+    message.build()
+}
+
+async fn record(store: &Store, entry: LogEntry) -> rusqlite::Result<()> {
+    store
+        .call(move |conn| {
+            conn.execute(
+                "INSERT INTO moderation_log (time, server, player, rule, nickname, outcome) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![entry.time, entry.server, entry.player, entry.rule, entry.nickname, entry.outcome],
+            )
+            .map(|_| ())
+        })
+        .await
+}
+
+/// Returns the latest `limit` log entries about the player with the given (case-insensitive)
+/// name, newest first.
+pub async fn history(
+    store: &Store,
+    player: String,
+    limit: usize,
+) -> rusqlite::Result<Vec<LogEntry>> {
+    store
+        .call(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT time, server, player, rule, nickname, outcome FROM moderation_log
+                WHERE player = ?1 COLLATE NOCASE
+                ORDER BY time DESC, id DESC
+                LIMIT ?2",
+            )?;
+
+            let rows = statement.query_map(params![player, limit as i64], |row| {
+                Ok(LogEntry {
+                    time: row.get(0)?,
+                    server: row.get(1)?,
+                    player: row.get(2)?,
+                    rule: row.get(3)?,
+                    nickname: row.get(4)?,
+                    outcome: row.get(5)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    fn rule(name: &str, pattern: &str, actions: &[ModerationAction]) -> ModerationRule {
+        ModerationRule {
+            name: name.into(),
+            pattern: Regex::new(pattern).unwrap(),
+            actions: actions.to_vec(),
+            ..ModerationRule::default()
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let config = ModerationConfig {
+            rules: vec![
+                rule("slurs", "(?i)badword", &[ModerationAction::Ban]),
+                rule("caps", "^[A-Z ]+$", &[ModerationAction::Notify]),
+            ],
+            ..ModerationConfig::default()
+        };
+
+        assert_eq!(config.find("BADWORD").unwrap().name, "slurs");
+        assert_eq!(config.find("LOUD").unwrap().name, "caps");
+        assert!(config.find("quiet").is_none());
+
+        assert!(config.rules[0].hides_nickname());
+        assert!(!config.rules[1].hides_nickname());
+    }
+
+    #[test]
+    fn default_rule_catches_links() {
+        let config = ModerationConfig::default();
+
+        assert!(config.find("discord.gg/abc").is_some());
+        assert!(config.find("d i s c o r d . g g").is_some());
+        assert!(config.find("https://example.com").is_some());
+        assert!(config.find("just a nickname").is_none());
+    }
+
+    #[tokio::test]
+    async fn history_is_per_player() {
+        let store = Store::open(":memory:", &[SCHEMA]).unwrap();
+        for (time, player) in [(1, "alex"), (2, "steve"), (3, "Alex")] {
+            let entry = LogEntry {
+                time,
+                server: "test".into(),
+                player: player.into(),
+                rule: "links".into(),
+                nickname: "discord.gg".into(),
+                outcome: "kick: Kicked".into(),
+            };
+            record(&store, entry).await.unwrap();
+        }
+
+        let history = history(&store, "ALEX".into(), 10).await.unwrap();
+        let times: Vec<i64> = history.iter().map(|entry| entry.time).collect();
+        assert_eq!(times, [3, 1]);
+        assert!(describe(&history[0]).contains("kick: Kicked"));
+    }
+
+    #[test]
+    fn verdicts_are_handled_once_per_session() {
+        let rule = rule("test", "x", &[ModerationAction::Notify]);
+        let verdict = || Verdict {
+            player: "alex",
+            nickname: "x".into(),
+            rule: &rule,
+        };
+        let mut moderator = Moderator::default();
+
+        assert_eq!(moderator.filter(vec![verdict()], ["alex"]).len(), 1);
+        assert!(moderator.filter(vec![verdict()], ["alex"]).is_empty());
+
+        // alex left and came back
+        assert!(moderator.filter(vec![], []).is_empty());
+        assert_eq!(moderator.filter(vec![verdict()], ["alex"]).len(), 1);
+    }
+}
@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+use rusqlite::params;
+
+use crate::chart::{Chart, Panel, TimeFormat};
+use crate::playtime::now;
+use crate::server_status::OnlineServerStatus;
+use crate::store::Store;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS perf_samples (
+    server TEXT NOT NULL,
+    time INTEGER NOT NULL,
+    players INTEGER NOT NULL,
+    -- NULL if the server doesn't report it
+    tps REAL,
+    mspt REAL
+);
+CREATE INDEX IF NOT EXISTS perf_samples_server_time ON perf_samples (server, time);
+";
+
+/// How often a sample is taken while the server is online.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long samples are kept for. This should be at least as long as the longest graph window.
+const RETENTION: i64 = 60 * 60 * 24 * 7;
+
+const TPS_COLOUR: [u8; 3] = [0x57, 0xf2, 0x87];
+const MSPT_COLOUR: [u8; 3] = [0xfe, 0xa6, 0x5c];
+const PLAYERS_COLOUR: [u8; 3] = [0x58, 0x65, 0xf2];
+
+pub struct Sample {
+    pub time: i64,
+    pub players: i32,
+    pub tps: Option<f32>,
+    pub mspt: Option<f32>,
+}
+
+/// Samples the status of a single server, at most once every [`SAMPLE_INTERVAL`].
+#[derive(Default)]
+pub struct Recorder {
+    last_sample: Option<Instant>,
+}
+
+impl Recorder {
+    pub async fn record(&mut self, store: &Store, server: &str, status: &OnlineServerStatus) {
+        if self
+            .last_sample
+            .is_some_and(|last| last.elapsed() < SAMPLE_INTERVAL)
+        {
+            return;
+        }
+        self.last_sample = Some(Instant::now());
+
+        let server = server.to_owned();
+        let now = now();
+        let players = status.current_players;
+        // the shortest window, so that lag spikes stand out
+        let tps = status.tps.map(|tps| tps[0]);
+        let mspt = status.mspt;
+
+        let result = store
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO perf_samples (server, time, players, tps, mspt) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![server, now, players, tps, mspt],
+                )?;
+                conn.execute(
+                    "DELETE FROM perf_samples WHERE server = ?1 AND time < ?2",
+                    params![server, now - RETENTION],
+                )
+            })
+            .await;
+
+        if let Err(why) = result {
+            log::error!("Couldn't record performance sample: {why}");
+        }
+    }
+}
+
+/// Returns the samples of `server` taken since `since`, oldest first.
+pub async fn samples(store: &Store, server: String, since: i64) -> rusqlite::Result<Vec<Sample>> {
+    store
+        .call(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT time, players, tps, mspt FROM perf_samples
+                WHERE server = ?1 AND time >= ?2
+                ORDER BY time",
+            )?;
+
+            let rows = statement.query_map(params![server, since], |row| {
+                Ok(Sample {
+                    time: row.get(0)?,
+                    players: row.get(1)?,
+                    tps: row.get(2)?,
+                    mspt: row.get(3)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+}
+
+/// Renders a chart of `samples` between `start` and `end`, with a panel each for TPS, MSPT and
+/// the player count. The TPS and MSPT panels are left out if none of the samples have them.
+pub fn graph(samples: &[Sample], start: i64, end: i64) -> Vec<u8> {
+    let tps: Vec<_> = samples
+        .iter()
+        .filter_map(|sample| Some((sample.time, sample.tps?)))
+        .collect();
+    let mspt: Vec<_> = samples
+        .iter()
+        .filter_map(|sample| Some((sample.time, sample.mspt?)))
+        .collect();
+    let players = samples
+        .iter()
+        .map(|sample| (sample.time, sample.players as f32))
+        .collect();
+
+    let mut panels = vec![];
+    if !tps.is_empty() {
+        panels.push(Panel {
+            title: "TPS",
+            colour: TPS_COLOUR,
+            points: tps,
+            min: 0.0,
+            max: 20.0,
+        });
+    }
+    if !mspt.is_empty() {
+        panels.push(Panel {
+            title: "MSPT",
+            colour: MSPT_COLOUR,
+            points: mspt,
+            min: 0.0,
+            // a tick taking longer than this is what makes the TPS drop
+            max: 50.0,
+        });
+    }
+    panels.push(Panel {
+        title: "Players",
+        colour: PLAYERS_COLOUR,
+        points: players,
+        min: 0.0,
+        max: 4.0,
+    });
+
+    let time_format = if end - start > 60 * 60 * 24 * 2 {
+        TimeFormat::Date
+    } else {
+        TimeFormat::Time
+    };
+
+    Chart {
+        panels,
+        start,
+        end,
+        // the server was offline (or the bot wasn't running) in between
+        max_gap: SAMPLE_INTERVAL.as_secs() as i64 * 3,
+        time_format,
+    }
+    .render()
+}
@@ -0,0 +1,182 @@
+use poise::serenity_prelude::{ChannelId, RoleId, UserId};
+
+use crate::config::{CommandPermission, PermissionsConfig, Tier};
+
+/// Whoever is using a command.
+pub struct Invoker<'a> {
+    pub user_id: UserId,
+    pub roles: &'a [RoleId],
+    /// `None` if the invoker isn't even a member.
+    pub tier: Option<Tier>,
+    pub channel_id: ChannelId,
+}
+
+/// Why a command can't be used.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Denial {
+    /// The command can only be used in other channels.
+    Channel,
+    /// The command requires this tier.
+    Tier(Tier),
+}
+
+impl PermissionsConfig {
+    /// Returns the highest tier of a member with `roles`. `op_role_id` is always an admin role.
+    pub fn tier(&self, op_role_id: RoleId, roles: &[RoleId]) -> Option<Tier> {
+        let has_any = |ids: &[u64]| ids.iter().any(|id| roles.contains(&RoleId::new(*id)));
+
+        if roles.contains(&op_role_id) || has_any(&self.admin_role_ids) {
+            Some(Tier::Admin)
+        } else if has_any(&self.moderator_role_ids) {
+            Some(Tier::Moderator)
+        } else if self.member_role_ids.is_empty() || has_any(&self.member_role_ids) {
+            Some(Tier::Member)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the permissions configured for the command with the given full name, falling back
+    /// to those of the groups it is in (e.g. `whitelist` for `whitelist remove mc_username`).
+    pub fn command(&self, name: &str) -> Option<&CommandPermission> {
+        let mut name = name;
+        loop {
+            if let Some(command) = self.commands.iter().find(|command| command.name == name) {
+                return Some(command);
+            }
+            name = &name[..name.rfind(' ')?];
+        }
+    }
+
+    /// Checks whether `invoker` may use the command with the given full name, whose tier is
+    /// `default_tier` unless the config says otherwise.
+    pub fn check(&self, name: &str, default_tier: Tier, invoker: &Invoker) -> Result<(), Denial> {
+        let command = self.command(name);
+        let tier = command
+            .and_then(|command| command.tier)
+            .unwrap_or(default_tier);
+
+        let Some(command) = command else {
+            return match invoker.tier >= Some(tier) {
+                true => Ok(()),
+                false => Err(Denial::Tier(tier)),
+            };
+        };
+
+        if !command.channel_ids.is_empty()
+            && !command.channel_ids.contains(&invoker.channel_id.get())
+        {
+            return Err(Denial::Channel);
+        }
+
+        let listed = command.user_ids.contains(&invoker.user_id.get())
+            || command
+                .role_ids
+                .iter()
+                .any(|id| invoker.roles.contains(&RoleId::new(*id)));
+        if listed || invoker.tier >= Some(tier) {
+            Ok(())
+        } else {
+            Err(Denial::Tier(tier))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OP_ROLE: RoleId = RoleId::new(1);
+    const MODERATOR_ROLE: RoleId = RoleId::new(2);
+    const HELPER_ROLE: RoleId = RoleId::new(3);
+
+    fn config() -> PermissionsConfig {
+        PermissionsConfig {
+            moderator_role_ids: vec![MODERATOR_ROLE.get()],
+            commands: vec![
+                CommandPermission {
+                    name: "whitelist".into(),
+                    tier: Some(Tier::Moderator),
+                    role_ids: vec![HELPER_ROLE.get()],
+                    user_ids: vec![42],
+                    channel_ids: vec![],
+                },
+                CommandPermission {
+                    name: "whitelist list".into(),
+                    tier: None,
+                    role_ids: vec![],
+                    user_ids: vec![],
+                    channel_ids: vec![100],
+                },
+            ],
+            ..PermissionsConfig::default()
+        }
+    }
+
+    fn invoker<'a>(config: &PermissionsConfig, user_id: u64, roles: &'a [RoleId]) -> Invoker<'a> {
+        Invoker {
+            user_id: UserId::new(user_id),
+            roles,
+            tier: config.tier(OP_ROLE, roles),
+            channel_id: ChannelId::new(100),
+        }
+    }
+
+    #[test]
+    fn tiers() {
+        let mut config = config();
+        assert_eq!(config.tier(OP_ROLE, &[OP_ROLE]), Some(Tier::Admin));
+        assert_eq!(
+            config.tier(OP_ROLE, &[HELPER_ROLE, MODERATOR_ROLE]),
+            Some(Tier::Moderator)
+        );
+        assert_eq!(config.tier(OP_ROLE, &[]), Some(Tier::Member));
+
+        config.member_role_ids = vec![HELPER_ROLE.get()];
+        assert_eq!(config.tier(OP_ROLE, &[HELPER_ROLE]), Some(Tier::Member));
+        assert_eq!(config.tier(OP_ROLE, &[]), None);
+    }
+
+    #[test]
+    fn commands() {
+        let config = config();
+        let member = invoker(&config, 5, &[]);
+        let helper = invoker(&config, 5, &[HELPER_ROLE]);
+        let moderator = invoker(&config, 5, &[MODERATOR_ROLE]);
+
+        assert_eq!(config.check("source", Tier::Member, &member), Ok(()));
+        assert_eq!(
+            config.check("run", Tier::Admin, &moderator),
+            Err(Denial::Tier(Tier::Admin))
+        );
+
+        // the group's tier replaces the default one
+        assert_eq!(
+            config.check("whitelist add", Tier::Admin, &moderator),
+            Ok(())
+        );
+        assert_eq!(config.check("whitelist add", Tier::Admin, &helper), Ok(()));
+        assert_eq!(
+            config.check("whitelist add", Tier::Admin, &invoker(&config, 42, &[])),
+            Ok(())
+        );
+        assert_eq!(
+            config.check("whitelist add", Tier::Admin, &member),
+            Err(Denial::Tier(Tier::Moderator))
+        );
+
+        // but not the subcommand's own entry
+        assert_eq!(
+            config.check("whitelist list", Tier::Member, &member),
+            Ok(())
+        );
+        let elsewhere = Invoker {
+            channel_id: ChannelId::new(200),
+            ..invoker(&config, 5, &[OP_ROLE])
+        };
+        assert_eq!(
+            config.check("whitelist list", Tier::Member, &elsewhere),
+            Err(Denial::Channel)
+        );
+    }
+}
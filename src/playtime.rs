@@ -0,0 +1,303 @@
+use std::time::{Duration, SystemTime};
+
+use rusqlite::{params, OptionalExtension};
+use tokio::sync::broadcast;
+
+use crate::events::{Player, ServerEvent, ServerEventKind};
+use crate::store::Store;
+use crate::Data;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    server TEXT NOT NULL,
+    -- the player's uuid, or their name prefixed with \"name:\" if the uuid is unknown
+    player_key TEXT NOT NULL,
+    player_name TEXT NOT NULL,
+    start INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    -- NULL while the session is ongoing
+    end INTEGER
+);
+CREATE INDEX IF NOT EXISTS sessions_player_key ON sessions (player_key);
+CREATE INDEX IF NOT EXISTS sessions_player_name ON sessions (player_name COLLATE NOCASE);
+";
+
+/// How often ongoing sessions are marked as such. If the bot stops unexpectedly, this is how much
+/// playtime can be lost per session.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The total playtime of a single player.
+pub struct Playtime {
+    pub name: String,
+    pub seconds: i64,
+}
+
+/// Records play sessions using the join/leave `events`, which should include the initial ones.
+pub async fn track(data: Data, mut events: broadcast::Receiver<ServerEvent>) {
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    // sessions that were ongoing when the bot stopped end at the last time they were seen. the
+    // players that are still online will get new sessions from the initial join events.
+    if let Err(why) = data
+        .store
+        .call(|conn| conn.execute("UPDATE sessions SET end = last_seen WHERE end IS NULL", []))
+        .await
+    {
+        log::error!("Couldn't close unfinished sessions: {why}");
+    }
+
+    loop {
+        let result = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => handle_event(&data.store, &event.server, event.kind).await,
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    log::warn!("Missed {count} events while tracking playtime");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = heartbeat.tick() => {
+                let now = now();
+                data.store
+                    .call(move |conn| {
+                        conn.execute("UPDATE sessions SET last_seen = ?1 WHERE end IS NULL", [now])
+                    })
+                    .await
+                    .map(|_| ())
+            }
+        };
+
+        if let Err(why) = result {
+            log::error!("Couldn't record playtime: {why}");
+        }
+    }
+}
+
+async fn handle_event(store: &Store, server: &str, kind: ServerEventKind) -> rusqlite::Result<()> {
+    let server = server.to_owned();
+    let now = now();
+
+    store
+        .call(move |conn| {
+            match kind {
+                ServerEventKind::PlayerJoined(player) => {
+                    let key = player_key(&player);
+                    conn.execute(
+                        "UPDATE sessions SET end = ?1 WHERE server = ?2 AND player_key = ?3 AND end IS NULL",
+                        params![now, server, key],
+                    )?;
+                    conn.execute(
+                        "INSERT INTO sessions (server, player_key, player_name, start, last_seen) VALUES (?1, ?2, ?3, ?4, ?4)",
+                        params![server, key, player.name, now],
+                    )?;
+                }
+                ServerEventKind::PlayerLeft(player) => {
+                    conn.execute(
+                        "UPDATE sessions SET end = ?1 WHERE server = ?2 AND player_key = ?3 AND end IS NULL",
+                        params![now, server, player_key(&player)],
+                    )?;
+                }
+                ServerEventKind::ServerWentOffline => {
+                    conn.execute(
+                        "UPDATE sessions SET end = ?1 WHERE server = ?2 AND end IS NULL",
+                        params![now, server],
+                    )?;
+                }
+                ServerEventKind::ServerWentOnline => {}
+            }
+            Ok(())
+        })
+        .await
+}
+
+fn player_key(player: &Player) -> String {
+    match player.uuid {
+        Some(uuid) => uuid.as_uuid().to_string(),
+        None => format!("name:{}", player.name),
+    }
+}
+
+/// Returns the playtime of the player with the given (case-insensitive) name since `since`, on
+/// `server` or on every server. If the player has never played, returns None.
+pub async fn player_playtime(
+    store: &Store,
+    name: String,
+    server: Option<String>,
+    since: i64,
+) -> rusqlite::Result<Option<Playtime>> {
+    let now = now();
+
+    store
+        .call(move |conn| {
+            // the bare player_name column is taken from the row with the latest start, so this
+            // returns the player's current name even if they've changed it since
+            conn.query_row(
+                "SELECT player_name, MAX(start), SUM(MAX(0, COALESCE(end, ?1) - MAX(start, ?2)))
+                FROM sessions
+                WHERE player_key IN (SELECT player_key FROM sessions WHERE player_name = ?3 COLLATE NOCASE)
+                AND (?4 IS NULL OR server = ?4)
+                HAVING COUNT(*) > 0",
+                params![now, since, name, server],
+                |row| {
+                    Ok(Playtime {
+                        name: row.get(0)?,
+                        seconds: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await
+}
+
+/// Returns the `limit` players who played the most since `since`, on `server` or on every server.
+pub async fn top(
+    store: &Store,
+    server: Option<String>,
+    since: i64,
+    limit: usize,
+) -> rusqlite::Result<Vec<Playtime>> {
+    let now = now();
+
+    store
+        .call(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT player_name, MAX(start), SUM(MAX(0, COALESCE(end, ?1) - MAX(start, ?2))) AS total
+                FROM sessions
+                WHERE COALESCE(end, ?1) > ?2 AND (?3 IS NULL OR server = ?3)
+                GROUP BY player_key
+                ORDER BY total DESC
+                LIMIT ?4",
+            )?;
+
+            let rows = statement.query_map(params![now, since, server, limit as i64], |row| {
+                Ok(Playtime {
+                    name: row.get(0)?,
+                    seconds: row.get(2)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+}
+
+/// The current unix time, in seconds.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// Formats a duration as e.g. "3d 4h 5m".
+pub fn format_duration(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    let (days, hours, minutes) = (minutes / (60 * 24), minutes / 60 % 24, minutes % 60);
+
+    let mut parts = vec![];
+    if days > 0 {
+        parts.push(format!("{days}d"));
+    }
+    if hours > 0 {
+        parts.push(format!("{hours}h"));
+    }
+    if minutes > 0 || parts.is_empty() {
+        parts.push(format!("{minutes}m"));
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(name: &str) -> Player {
+        Player {
+            name: name.to_owned(),
+            uuid: None,
+        }
+    }
+
+    async fn session(store: &Store, server: &str, name: &str, start: i64, end: i64) {
+        let (server, key, name) = (
+            server.to_owned(),
+            player_key(&player(name)),
+            name.to_owned(),
+        );
+        store
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO sessions (server, player_key, player_name, start, last_seen, end) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                    params![server, key, name, start, end],
+                )
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sessions_are_recorded() {
+        let store = Store::open(":memory:", &[SCHEMA]).unwrap();
+        handle_event(
+            &store,
+            "test",
+            ServerEventKind::PlayerJoined(player("alex")),
+        )
+        .await
+        .unwrap();
+        handle_event(&store, "test", ServerEventKind::ServerWentOffline)
+            .await
+            .unwrap();
+
+        let open: i64 = store
+            .call(|conn| {
+                conn.query_row(
+                    "SELECT COUNT(*) FROM sessions WHERE end IS NULL",
+                    [],
+                    |row| row.get(0),
+                )
+            })
+            .await
+            .unwrap();
+        assert_eq!(open, 0);
+        assert!(player_playtime(&store, "ALEX".into(), None, 0)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(player_playtime(&store, "steve".into(), None, 0)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn leaderboard() {
+        let store = Store::open(":memory:", &[SCHEMA]).unwrap();
+        session(&store, "a", "alex", 100, 400).await;
+        session(&store, "b", "alex", 500, 600).await;
+        session(&store, "a", "steve", 0, 350).await;
+
+        let all = top(&store, None, 0, 10).await.unwrap();
+        let all: Vec<_> = all.iter().map(|p| (&*p.name, p.seconds)).collect();
+        assert_eq!(all, [("alex", 400), ("steve", 350)]);
+
+        // only the part of a session inside the period counts
+        let top_a = top(&store, Some("a".into()), 200, 10).await.unwrap();
+        let top_a: Vec<_> = top_a.iter().map(|p| (&*p.name, p.seconds)).collect();
+        assert_eq!(top_a, [("alex", 200), ("steve", 150)]);
+
+        let alex = player_playtime(&store, "alex".into(), Some("b".into()), 0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(alex.seconds, 100);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(0), "0m");
+        assert_eq!(format_duration(3 * 86400 + 4 * 3600 + 5 * 60), "3d 4h 5m");
+        assert_eq!(format_duration(7200), "2h");
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use poise::serenity_prelude::{ChannelId, Http};
+use tokio::task::JoinHandle;
+
+use crate::config::{self, Config};
+use crate::settings::Settings;
+use crate::Data;
+
+/// How often the config file is checked for modifications.
+const POLL_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
+/// The running [`list_updater`](crate::list_updater), [`tail`](crate::server_log::tail) and
+/// [`countdown`](crate::restarts::countdown) tasks, one of each per server.
+#[derive(Default)]
+pub struct ListUpdaters {
+    tasks: HashMap<Box<str>, (ChannelId, JoinHandle<()>)>,
+    tailers: HashMap<Box<str>, JoinHandle<()>>,
+    countdowns: HashMap<Box<str>, JoinHandle<()>>,
+}
+
+impl ListUpdaters {
+    /// Makes sure there's exactly one updater for every server in `settings`, restarting the ones
+    /// whose list channel changed.
+    pub async fn sync(&mut self, data: &Data, http: &Arc<Http>, settings: &Settings) {
+        let mut cache = data.cache.lock().await;
+
+        self.tasks.retain(|name, (channel_id, task)| {
+            let keep = settings
+                .server(name)
+                .is_some_and(|server| server.list_channel_id == *channel_id);
+            if !keep {
+                task.abort();
+                cache.remove(name);
+            }
+            keep
+        });
+        for tasks in [&mut self.tailers, &mut self.countdowns] {
+            tasks.retain(|name, task| {
+                let keep = settings.server(name).is_some();
+                if !keep {
+                    task.abort();
+                }
+                keep
+            });
+        }
+
+        for server in &settings.servers {
+            if !self.tailers.contains_key(&server.name) {
+                let _data = data.clone();
+                let _name = server.name.clone();
+                let task = tokio::spawn(async move { crate::server_log::tail(_data, _name).await });
+                self.tailers.insert(server.name.clone(), task);
+            }
+            if !self.countdowns.contains_key(&server.name) {
+                let _data = data.clone();
+                let _name = server.name.clone();
+                let task =
+                    tokio::spawn(async move { crate::restarts::countdown(_data, _name).await });
+                self.countdowns.insert(server.name.clone(), task);
+            }
+
+            if self.tasks.contains_key(&server.name) {
+                continue;
+            }
+
+            let _data = data.clone();
+            let _name = server.name.clone();
+            let _http = Arc::clone(http);
+            let task = tokio::spawn(async move { crate::list_updater(_data, _name, _http).await });
+
+            self.tasks
+                .insert(server.name.clone(), (server.list_channel_id, task));
+        }
+    }
+}
+
+/// Reloads the config whenever the config file is modified or the process receives SIGHUP.
+/// Note that environment variables can't change while the bot is running, so any overrides
+/// stay the same.
+pub async fn watch(
+    data: Data,
+    http: Arc<Http>,
+    path: PathBuf,
+    explicit: bool,
+    mut config: Config,
+    mut updaters: ListUpdaters,
+) {
+    let mut hangup = Hangup::new();
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut last_modified = modified(&path).await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let modified = modified(&path).await;
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                log::info!("{} was modified, reloading the config", path.display());
+            }
+            _ = hangup.recv() => {
+                log::info!("Received SIGHUP, reloading the config");
+            }
+        }
+
+        let new_config = match config::load(&path, explicit) {
+            Ok(new_config) => new_config,
+            Err(errors) => {
+                log::error!(
+                    "{}\nKeeping the previous config.",
+                    config::format_errors(&path, &errors)
+                );
+                continue;
+            }
+        };
+
+        let changes = diff(&config, &new_config);
+        if changes.is_empty() {
+            log::info!("The config didn't change.");
+            continue;
+        }
+
+        let settings = Arc::new(Settings::new(&new_config, Some(&data.settings())).await);
+        *data.settings.write().unwrap() = Arc::clone(&settings);
+        updaters.sync(&data, &http, &settings).await;
+
+        log::info!("Reloaded the config:\n- {}", changes.join("\n- "));
+        config = new_config;
+    }
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+/// Describes the differences between two configs, one line per change.
+fn diff(old: &Config, new: &Config) -> Vec<String> {
+    let mut changes = vec![];
+
+    if old.discord.token != new.discord.token {
+        changes.push("discord.token changed (this only takes effect after a restart)".into());
+    }
+    if old.discord.op_role_id != new.discord.op_role_id {
+        changes.push(format!(
+            "discord.op_role_id: {} -> {}",
+            old.discord.op_role_id, new.discord.op_role_id
+        ));
+    }
+    if old.storage.path != new.storage.path {
+        changes.push("storage.path changed (this only takes effect after a restart)".into());
+    }
+    if old.database != new.database {
+        changes.push("the database settings changed".into());
+    }
+    if old.audit != new.audit {
+        changes.push(format!(
+            "audit.channel_id: {:?} -> {:?}",
+            old.audit.channel_id, new.audit.channel_id
+        ));
+    }
+    if old.permissions != new.permissions {
+        changes.push("the command permissions changed".into());
+    }
+    if old.run != new.run {
+        changes.push("the /run settings changed".into());
+    }
+    if old.moderation != new.moderation {
+        changes.push("the moderation rules changed".into());
+    }
+    if old.list.style != new.list.style {
+        changes.push(format!(
+            "list.style: {:?} -> {:?}",
+            old.list.style, new.list.style
+        ));
+    }
+    if old.list.head_url != new.list.head_url {
+        changes.push(format!(
+            "list.head_url: {:?} -> {:?}",
+            old.list.head_url, new.list.head_url
+        ));
+    }
+    if old.list.low_tps != new.list.low_tps {
+        changes.push(format!(
+            "list.low_tps: {} -> {}",
+            old.list.low_tps, new.list.low_tps
+        ));
+    }
+    if old.alerts != new.alerts {
+        changes.push("the alert settings changed".into());
+    }
+
+    for server in &old.servers {
+        if !new.servers.iter().any(|new| new.name == server.name) {
+            changes.push(format!("server {} was removed", server.name));
+        }
+    }
+
+    for server in &new.servers {
+        let name = &server.name;
+        let Some(old) = old.servers.iter().find(|old| old.name == *name) else {
+            changes.push(format!("server {name} was added"));
+            continue;
+        };
+
+        if old.directory != server.directory {
+            changes.push(format!(
+                "server.{name}.directory: {} -> {}",
+                old.directory, server.directory
+            ));
+        }
+        if old.list_channel_id != server.list_channel_id {
+            changes.push(format!(
+                "server.{name}.list_channel_id: {} -> {}",
+                old.list_channel_id, server.list_channel_id
+            ));
+        }
+        if old.events_channel_id != server.events_channel_id {
+            changes.push(format!(
+                "server.{name}.events_channel_id: {:?} -> {:?}",
+                old.events_channel_id, server.events_channel_id
+            ));
+        }
+        if old.chat_channel_id != server.chat_channel_id {
+            changes.push(format!(
+                "server.{name}.chat_channel_id: {:?} -> {:?}",
+                old.chat_channel_id, server.chat_channel_id
+            ));
+        }
+        if old.rcon.address != server.rcon.address {
+            changes.push(format!(
+                "server.{name}.rcon.address: {} -> {}",
+                old.rcon.address, server.rcon.address
+            ));
+        }
+        if old.rcon.password != server.rcon.password {
+            changes.push(format!("server.{name}.rcon.password changed"));
+        }
+        if old.features.list_json != server.features.list_json {
+            changes.push(format!(
+                "server.{name}.features.list_json: {} -> {}",
+                old.features.list_json, server.features.list_json
+            ));
+        }
+        if old.features.easyauth != server.features.easyauth {
+            changes.push(format!(
+                "server.{name}.features.easyauth: {} -> {}",
+                old.features.easyauth, server.features.easyauth
+            ));
+        }
+        if old.status.sources != server.status.sources {
+            changes.push(format!(
+                "server.{name}.status.sources: {:?} -> {:?}",
+                old.status.sources, server.status.sources
+            ));
+        }
+        if old.status.slp_address != server.status.slp_address {
+            changes.push(format!(
+                "server.{name}.status.slp_address: {} -> {}",
+                old.status.slp_address, server.status.slp_address
+            ));
+        }
+        if old.status.query_address != server.status.query_address {
+            changes.push(format!(
+                "server.{name}.status.query_address: {} -> {}",
+                old.status.query_address, server.status.query_address
+            ));
+        }
+        if old.daily_restarts != server.daily_restarts {
+            changes.push(format!("server.{name}.daily_restarts changed"));
+        }
+        if old.announcements != server.announcements {
+            changes.push(format!("server.{name}.announcements changed"));
+        }
+        if old.supervisor != server.supervisor {
+            changes.push(format!(
+                "server.{name}.supervisor changed (a new command line takes effect the next time the server starts)"
+            ));
+        }
+    }
+
+    changes
+}
+
+/// Receives SIGHUP. On non-unix platforms this never resolves.
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            let signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup());
+            if let Err(why) = &signal {
+                log::warn!("Couldn't listen for SIGHUP: {why}");
+            }
+            Self {
+                signal: signal.ok(),
+            }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending().await
+    }
+}
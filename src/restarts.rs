@@ -0,0 +1,373 @@
+//! Restarts scheduled from discord or the config, with countdowns in the game beforehand.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::params;
+use serde_json::json;
+
+use crate::config::TimeOfDay;
+use crate::server::Server;
+use crate::settings::Settings;
+use crate::store::Store;
+use crate::supervisor::{Action, State};
+use crate::Data;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS scheduled_restarts (
+    server TEXT PRIMARY KEY,
+    -- the unix time of the restart, or NULL to restart once nobody is online
+    time INTEGER
+);
+";
+
+/// How many seconds before a restart the players are warned.
+const WARNINGS: [i64; 4] = [600, 300, 60, 10];
+
+/// Restarts which were missed by more than this (because the bot wasn't running) are dropped
+/// instead of happening late.
+const MAX_LATENESS: i64 = 300;
+
+const DAY: i64 = 60 * 60 * 24;
+
+/// A restart scheduled with `/schedule_restart`, which happens once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduledRestart {
+    /// As soon as nobody is online.
+    WhenEmpty,
+    /// At the given unix time.
+    At(i64),
+}
+
+/// Replaces the scheduled restart of `server`, and saves it so it survives a restart of the bot.
+pub async fn set(
+    store: &Store,
+    server: &Server,
+    restart: Option<ScheduledRestart>,
+) -> rusqlite::Result<()> {
+    *server.scheduled_restart.lock().await = restart;
+
+    let name = server.name.to_string();
+    store
+        .call(move |conn| match restart {
+            None => conn
+                .execute("DELETE FROM scheduled_restarts WHERE server = ?1", [name])
+                .map(drop),
+            Some(restart) => conn
+                .execute(
+                    "INSERT OR REPLACE INTO scheduled_restarts (server, time) VALUES (?1, ?2)",
+                    params![
+                        name,
+                        match restart {
+                            ScheduledRestart::WhenEmpty => None,
+                            ScheduledRestart::At(time) => Some(time),
+                        }
+                    ],
+                )
+                .map(drop),
+        })
+        .await
+}
+
+/// Restores the restarts scheduled before the bot was restarted.
+pub async fn load(store: &Store, settings: &Settings) -> rusqlite::Result<()> {
+    let restarts = store
+        .call(|conn| {
+            let mut statement = conn.prepare("SELECT server, time FROM scheduled_restarts")?;
+            let rows = statement.query_map([], |row| {
+                let time: Option<i64> = row.get(1)?;
+                Ok((
+                    row.get::<_, String>(0)?,
+                    time.map_or(ScheduledRestart::WhenEmpty, ScheduledRestart::At),
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .await?;
+
+    for (name, restart) in restarts {
+        // the restarts of servers which were removed from the config stay until they come back
+        if let Some(server) = settings.server(&name) {
+            *server.scheduled_restart.lock().await = Some(restart);
+        }
+    }
+    Ok(())
+}
+
+/// Restarts the server: through its supervisor if it has one, or else by stopping it and leaving the
+/// rest to whatever started it.
+pub async fn restart(server: &Server) {
+    let result = match &server.supervisor {
+        Some(supervisor) => match supervisor.request(Action::Status).await {
+            // a server which was stopped on purpose stays that way
+            Ok(status) if !matches!(status.state, State::Running { .. }) => return,
+            _ => supervisor
+                .request(Action::Restart)
+                .await
+                .map(drop)
+                .map_err(|why| why.to_string()),
+        },
+        None => (server.interface.lock().await.exec("stop").await)
+            .map(drop)
+            .map_err(|why| why.to_string()),
+    };
+    if let Err(why) = result {
+        log::warn!("Couldn't restart {}: {why}", server.name);
+    }
+}
+
+/// Warns the players of the server about upcoming restarts, and restarts it when the time comes.
+/// This handles the restarts at a set time; the ones for when nobody is online are done by the
+/// list updater.
+pub async fn countdown(data: Data, server_name: Box<str>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    // the daily restarts up to here have happened already
+    let mut last_restart = unix_time();
+    // the restart which was counted down to last, and how many seconds were left then
+    let mut previous: Option<(i64, i64)> = None;
+
+    loop {
+        interval.tick().await;
+
+        let settings = data.settings();
+        let Some(server) = settings.server(&server_name) else {
+            // the server was removed from the config
+            return;
+        };
+        let now = unix_time();
+
+        let at = match *server.scheduled_restart.lock().await {
+            Some(ScheduledRestart::At(time)) => Some(time),
+            _ => None,
+        };
+        if at.is_some_and(|at| now - at > MAX_LATENESS) {
+            log::warn!("Dropping the missed restart of {}", server.name);
+            if let Err(why) = set(&data.store, server, None).await {
+                eprintln!(
+                    "Couldn't save the scheduled restart of {}: {why}",
+                    server.name
+                );
+            }
+            continue;
+        }
+
+        let daily = next_daily(&server.daily_restarts, last_restart);
+        let Some(next) = at.into_iter().chain(daily).min() else {
+            previous = None;
+            continue;
+        };
+        let remaining = next - now;
+
+        if remaining <= 0 {
+            log::info!("Restarting {} as scheduled", server.name);
+            previous = None;
+            last_restart = now;
+            if at.is_some_and(|at| at <= now) {
+                if let Err(why) = set(&data.store, server, None).await {
+                    eprintln!(
+                        "Couldn't save the scheduled restart of {}: {why}",
+                        server.name
+                    );
+                }
+            }
+            restart(server).await;
+            continue;
+        }
+
+        let previous_remaining = previous
+            .filter(|(time, _)| *time == next)
+            .map(|(_, remaining)| remaining);
+        if is_warning(previous_remaining, remaining) {
+            warn(server, remaining).await;
+        }
+        previous = Some((next, remaining));
+    }
+}
+
+/// Tells the players how long it is until the restart, in the chat and above the hotbar.
+async fn warn(server: &Server, remaining: i64) {
+    let message = format!("The server will restart in {}.", describe(remaining));
+    let title = json!({ "text": message, "color": "red" });
+
+    let mut interface = server.interface.lock().await;
+    for command in [
+        format!("say {message}"),
+        format!("title @a actionbar {title}"),
+    ] {
+        // if this fails, the server is offline and there's nobody to warn anyway
+        if let Err(why) = interface.exec(&command).await {
+            log::warn!("Couldn't warn {} about the restart: {why}", server.name);
+            return;
+        }
+    }
+}
+
+/// Whether the players should be warned now that `remaining` seconds are left, if they were
+/// `previous` seconds ago (or if this is a new restart, when `previous` is `None`).
+fn is_warning(previous: Option<i64>, remaining: i64) -> bool {
+    match previous {
+        // this also warns about restarts scheduled less than 10 minutes ahead right away
+        None => remaining <= WARNINGS[0],
+        Some(previous) => WARNINGS
+            .iter()
+            .any(|warning| previous > *warning && remaining <= *warning),
+    }
+}
+
+fn describe(seconds: i64) -> String {
+    let (amount, unit) = match seconds {
+        60.. => ((seconds + 30) / 60, "minute"),
+        _ => (seconds, "second"),
+    };
+    match amount {
+        1 => format!("1 {unit}"),
+        _ => format!("{amount} {unit}s"),
+    }
+}
+
+/// Returns the first of the daily restarts after the unix time `after`.
+pub fn next_daily(times: &[TimeOfDay], after: i64) -> Option<i64> {
+    let today = after.div_euclid(DAY) * DAY;
+    [today, today + DAY]
+        .into_iter()
+        .flat_map(|day| times.iter().map(move |time| day + i64::from(time.0)))
+        .find(|time| *time > after)
+}
+
+/// Parses a time in UTC, either `HH:MM` for its next occurrence after `now` or `YYYY-MM-DD HH:MM`.
+pub fn parse_time(text: &str, now: i64) -> Option<i64> {
+    let text = text.trim();
+    let Some((date, time)) = text.split_once([' ', 'T']) else {
+        let time = TimeOfDay::parse(text)?;
+        return next_daily(&[time], now);
+    };
+
+    let time = TimeOfDay::parse(time)?;
+    let mut parts = date.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    let days_in_month = days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1);
+    if !(1..=12).contains(&month) || day == 0 || i64::from(day) > days_in_month {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * DAY + i64::from(time.0))
+}
+
+/// The number of days since 1970-01-01 (see http://howardhinnant.github.io/date_algorithms.html).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+pub fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rcon::MockRcon;
+
+    // 2024-03-10 12:00:00 UTC
+    const NOON: i64 = 1710072000;
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("2024-03-10 12:00", 0), Some(NOON));
+        assert_eq!(parse_time("1970-01-01 00:01", NOON), Some(60));
+        assert_eq!(parse_time("2024-02-29 00:00", 0), Some(1709164800));
+        assert_eq!(parse_time("2023-02-29 00:00", 0), None);
+        assert_eq!(parse_time("2024-13-01 00:00", 0), None);
+
+        assert_eq!(parse_time("13:30", NOON), Some(NOON + 90 * 60));
+        assert_eq!(parse_time("11:00", NOON), Some(NOON + 23 * 60 * 60));
+        assert_eq!(parse_time("12:00", NOON), Some(NOON + DAY));
+        assert_eq!(parse_time("24:00", NOON), None);
+        assert_eq!(parse_time("soon", NOON), None);
+    }
+
+    #[test]
+    fn daily() {
+        let times = [TimeOfDay(4 * 3600), TimeOfDay(16 * 3600)];
+        assert_eq!(next_daily(&times, NOON), Some(NOON + 4 * 3600));
+        assert_eq!(next_daily(&times, NOON + 4 * 3600), Some(NOON + 16 * 3600));
+        assert_eq!(next_daily(&[], NOON), None);
+    }
+
+    #[test]
+    fn warnings() {
+        assert!(!is_warning(None, 3600));
+        assert!(!is_warning(Some(602), 601));
+        assert!(is_warning(Some(601), 600));
+        assert!(!is_warning(Some(600), 599));
+        assert!(is_warning(Some(11), 10));
+        // new restarts are announced right away, if they're soon
+        assert!(is_warning(None, 180));
+
+        assert_eq!(describe(600), "10 minutes");
+        assert_eq!(describe(60), "1 minute");
+        assert_eq!(describe(10), "10 seconds");
+    }
+
+    #[tokio::test]
+    async fn persistence() {
+        let mock = MockRcon::start("password").await;
+        let store = Store::open(":memory:", &[SCHEMA]).unwrap();
+        let server = mock.server(false, ".");
+
+        set(&store, &server, Some(ScheduledRestart::At(NOON)))
+            .await
+            .unwrap();
+        let saved: Option<i64> = store
+            .call(|conn| {
+                conn.query_row("SELECT time FROM scheduled_restarts", [], |row| row.get(0))
+            })
+            .await
+            .unwrap();
+        assert_eq!(saved, Some(NOON));
+
+        set(&store, &server, Some(ScheduledRestart::WhenEmpty))
+            .await
+            .unwrap();
+        set(&store, &server, None).await.unwrap();
+        let count: i64 = store
+            .call(|conn| {
+                conn.query_row("SELECT COUNT(*) FROM scheduled_restarts", [], |row| {
+                    row.get(0)
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn warning_commands() {
+        let mock = MockRcon::start("password").await;
+        let server = mock.server(false, ".");
+        warn(&server, 300).await;
+        assert_eq!(
+            mock.commands(),
+            [
+                "say The server will restart in 5 minutes.",
+                r#"title @a actionbar {"color":"red","text":"The server will restart in 5 minutes."}"#,
+            ]
+        );
+    }
+}
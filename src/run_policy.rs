@@ -0,0 +1,234 @@
+use poise::serenity_prelude::RoleId;
+
+use crate::config::{CommandPattern, RunConfig, RunPolicy};
+
+/// Why a command can't be run.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Denial<'a> {
+    /// The command matches a deny pattern of one of the member's policies.
+    Denied {
+        policy: &'a RunPolicy,
+        pattern: &'a CommandPattern,
+    },
+    /// The member isn't an admin, and none of their policies allow the command.
+    NotAllowed,
+}
+
+impl CommandPattern {
+    /// Whether `command` (as typed into `/run`) starts with the pattern's words. Commands may
+    /// start with a slash, and the `minecraft:` namespace is ignored.
+    pub fn matches(&self, command: &str) -> bool {
+        self.matches_words(&command.split_whitespace().collect::<Vec<_>>())
+    }
+
+    /// Like [`matches`](Self::matches), but also matches the commands that `command` runs using
+    /// `execute ... run`, however deeply they're nested.
+    pub fn matches_nested(&self, command: &str) -> bool {
+        let words: Vec<_> = command.split_whitespace().collect();
+        if self.matches_words(&words) {
+            return true;
+        }
+
+        // every word after a `run` could start the command that's run, since a `run` can also be
+        // an argument (e.g. a player's name), and nested `execute`s are covered by the later `run`s
+        let is_execute = words
+            .first()
+            .is_some_and(|word| command_name(word) == "execute");
+        is_execute
+            && (1..words.len())
+                .filter(|&i| words[i - 1].eq_ignore_ascii_case("run"))
+                .any(|i| self.matches_words(&words[i..]))
+    }
+
+    fn matches_words(&self, words: &[&str]) -> bool {
+        let mut words = words.iter().enumerate().map(|(i, word)| match i {
+            0 => command_name(word),
+            _ => word.to_lowercase(),
+        });
+        self.words
+            .iter()
+            .all(|pattern| words.next().as_deref() == Some(pattern))
+    }
+}
+
+/// Normalizes the first word of a command: lowercase, without the slash and the `minecraft:`
+/// namespace.
+fn command_name(word: &str) -> String {
+    let word = word.trim_start_matches('/').to_lowercase();
+    match word.strip_prefix("minecraft:") {
+        Some(name) => name.to_owned(),
+        None => word,
+    }
+}
+
+impl std::fmt::Display for CommandPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.words.is_empty() {
+            f.write_str("*")
+        } else {
+            f.write_str(&self.words.join(" "))
+        }
+    }
+}
+
+impl RunConfig {
+    /// Checks whether a member with `roles` may run `command`. Admins may run anything which none of
+    /// their policies deny, including through `execute ... run`; everyone else needs a policy which
+    /// allows it.
+    pub fn check<'a>(
+        &'a self,
+        command: &str,
+        is_op: bool,
+        roles: &[RoleId],
+    ) -> Result<(), Denial<'a>> {
+        for policy in self.policies_of(roles) {
+            // denied commands can't be smuggled in through an allowed `execute`
+            let denied = policy
+                .deny
+                .iter()
+                .find(|pattern| pattern.matches_nested(command));
+            if let Some(pattern) = denied {
+                return Err(Denial::Denied { policy, pattern });
+            }
+        }
+
+        let allowed = is_op
+            || self
+                .policies_of(roles)
+                .any(|policy| policy.allow.iter().any(|pattern| pattern.matches(command)));
+        if allowed {
+            Ok(())
+        } else {
+            Err(Denial::NotAllowed)
+        }
+    }
+
+    /// Whether any of `roles` has a policy, which lets its members use `/run` at all.
+    pub fn has_policy(&self, roles: &[RoleId]) -> bool {
+        self.policies_of(roles).next().is_some()
+    }
+
+    fn policies_of<'a: 'b, 'b>(
+        &'a self,
+        roles: &'b [RoleId],
+    ) -> impl Iterator<Item = &'a RunPolicy> + 'b {
+        self.policies
+            .iter()
+            .filter(|policy| roles.contains(&RoleId::new(policy.role_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(pattern: &str) -> CommandPattern {
+        CommandPattern {
+            words: pattern.split_whitespace().map(str::to_owned).collect(),
+        }
+    }
+
+    fn config() -> RunConfig {
+        RunConfig {
+            policies: vec![
+                RunPolicy {
+                    name: "moderators".into(),
+                    role_id: 1,
+                    allow: vec![pattern("kick"), pattern("tp"), pattern("whitelist add")],
+                    deny: vec![],
+                },
+                RunPolicy {
+                    name: "no stopping".into(),
+                    role_id: 2,
+                    allow: vec![],
+                    deny: vec![pattern("stop"), pattern("op"), pattern("deop")],
+                },
+                RunPolicy {
+                    name: "executors".into(),
+                    role_id: 3,
+                    allow: vec![pattern("execute")],
+                    deny: vec![],
+                },
+            ],
+            ..RunConfig::default()
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(pattern("kick").matches("kick alex"));
+        assert!(pattern("kick").matches("/minecraft:KICK alex"));
+        assert!(!pattern("kick").matches("kickall"));
+        assert!(pattern("whitelist add").matches("whitelist  add alex"));
+        assert!(!pattern("whitelist add").matches("whitelist remove alex"));
+        assert!(!pattern("whitelist add").matches("whitelist"));
+        assert!(pattern("").matches("anything at all"));
+    }
+
+    #[test]
+    fn policies() {
+        let config = config();
+        let moderator = [RoleId::new(1), RoleId::new(2)];
+
+        assert_eq!(config.check("kick alex", false, &moderator), Ok(()));
+        assert_eq!(
+            config.check("whitelist remove alex", false, &moderator),
+            Err(Denial::NotAllowed)
+        );
+        let Err(Denial::Denied { policy, pattern }) = config.check("op alex", false, &moderator)
+        else {
+            panic!("op wasn't denied");
+        };
+        assert_eq!(
+            (&*policy.name, pattern.to_string()),
+            ("no stopping", "op".into())
+        );
+
+        // admins can run anything their policies don't deny
+        assert_eq!(config.check("time set day", true, &[]), Ok(()));
+        assert!(config.check("stop", true, &[RoleId::new(2)]).is_err());
+
+        let executor = [RoleId::new(2), RoleId::new(3)];
+        assert_eq!(config.check("execute run say hi", false, &executor), Ok(()));
+        let Err(Denial::Denied { policy, pattern }) =
+            config.check("execute as @a run op alex", false, &executor)
+        else {
+            panic!("op wasn't denied inside execute");
+        };
+        assert_eq!(
+            (&*policy.name, pattern.to_string()),
+            ("no stopping", "op".into())
+        );
+        assert!(config
+            .check("execute run stop", true, &[RoleId::new(2)])
+            .is_err());
+
+        assert!(config.has_policy(&moderator));
+        assert!(!config.has_policy(&[RoleId::new(4)]));
+    }
+
+    #[test]
+    fn default_confirmations() {
+        let confirm = RunConfig::default().confirm;
+        let needs_confirmation = |command| {
+            confirm
+                .iter()
+                .any(|pattern| pattern.matches_nested(command))
+        };
+
+        assert!(needs_confirmation("stop"));
+        assert!(needs_confirmation("/kill @e"));
+        assert!(needs_confirmation("op alex"));
+        assert!(!needs_confirmation("kill @e[type=item]"));
+        assert!(!needs_confirmation("list"));
+
+        assert!(needs_confirmation("execute run stop"));
+        assert!(needs_confirmation("/execute as @a run minecraft:kill @e"));
+        assert!(needs_confirmation(
+            "execute as alex run execute at @s run op alex"
+        ));
+        assert!(needs_confirmation("execute as run run stop"));
+        assert!(!needs_confirmation("execute as @a run kill @s"));
+        assert!(!needs_confirmation("say run stop"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude::ChannelId;
+use tokio::sync::Mutex;
+
+use crate::config::{AnnouncementsConfig, ServerConfig, StatusSource, TimeOfDay};
+use crate::interface::Interface;
+use crate::restarts::ScheduledRestart;
+use crate::supervisor::Supervisor;
+
+/// A single minecraft server managed by the bot.
+pub struct Server {
+    pub name: Box<str>,
+    pub interface: Arc<Mutex<Interface>>,
+    pub list_channel_id: ChannelId,
+    pub events_channel_id: Option<ChannelId>,
+    pub chat_channel_id: Option<ChannelId>,
+    pub scheduled_restart: Arc<Mutex<Option<ScheduledRestart>>>,
+    pub daily_restarts: Box<[TimeOfDay]>,
+    pub has_list_json: bool,
+    pub has_easyauth: bool,
+    pub directory: Box<str>,
+    pub status_sources: Box<[StatusSource]>,
+    pub slp_address: Box<str>,
+    pub query_address: Box<str>,
+    pub announcements: Option<AnnouncementsConfig>,
+    /// Set if the bot runs the server's process itself.
+    pub supervisor: Option<Arc<Supervisor>>,
+}
+
+impl Server {
+    /// Creates a server from its config. If the server existed before a config reload, `previous`
+    /// should be set to it so that its runtime state (and RCON connection, if the credentials haven't
+    /// changed) is carried over.
+    pub async fn new(config: &ServerConfig, previous: Option<&Server>) -> Self {
+        let mut interface = None;
+        if let Some(previous) = previous {
+            if previous
+                .interface
+                .lock()
+                .await
+                .has_credentials(&config.rcon.address, &config.rcon.password)
+            {
+                interface = Some(Arc::clone(&previous.interface));
+            }
+        }
+
+        let addresses = [&*config.rcon.address, &*config.status.slp_address];
+        let supervisor = config.supervisor.as_ref().map(|supervisor_config| {
+            match previous.and_then(|previous| previous.supervisor.as_ref()) {
+                Some(supervisor) => {
+                    supervisor.update(supervisor_config, &config.directory, &addresses);
+                    Arc::clone(supervisor)
+                }
+                None => Arc::new(Supervisor::new(
+                    supervisor_config,
+                    &config.directory,
+                    &addresses,
+                )),
+            }
+        });
+
+        Self {
+            name: config.name.clone().into_boxed_str(),
+            interface: interface.unwrap_or_else(|| {
+                Arc::new(Mutex::new(Interface::new(
+                    &config.rcon.address,
+                    &config.rcon.password,
+                )))
+            }),
+            list_channel_id: ChannelId::new(config.list_channel_id),
+            events_channel_id: config.events_channel_id.map(ChannelId::new),
+            chat_channel_id: config.chat_channel_id.map(ChannelId::new),
+            scheduled_restart: previous
+                .map(|previous| Arc::clone(&previous.scheduled_restart))
+                .unwrap_or_default(),
+            daily_restarts: config.daily_restarts.clone().into_boxed_slice(),
+            has_list_json: config.features.list_json,
+            has_easyauth: config.features.easyauth,
+            directory: config.directory.clone().into_boxed_str(),
+            status_sources: config.status.sources.clone().into_boxed_slice(),
+            slp_address: config.status.slp_address.clone().into_boxed_str(),
+            query_address: config.status.query_address.clone().into_boxed_str(),
+            announcements: config.announcements.clone(),
+            supervisor,
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! Follows a server's `logs/latest.log` and turns its lines into events.
+
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::Data;
+
+/// How many events can be buffered before slow subscribers start missing them.
+pub const CHANNEL_CAPACITY: usize = 256;
+
+/// How often the log is checked for new lines.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `[12:34:56] [Server thread/INFO]: message`, as written by vanilla and most modded servers.
+static VANILLA_LINE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\[[\d:.]+\] \[([^\]]*)/([A-Z]+)\]: (.*)$").unwrap());
+/// `[12:34:56 INFO]: message`, as written by Paper and its forks.
+static PAPER_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\[[\d:.]+ ([A-Z]+)\]: (.*)$").unwrap());
+
+static CHAT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:\[Not Secure\] )?<(\w{1,16})> (.*)$").unwrap());
+static JOINED: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\w{1,16}) joined the game$").unwrap());
+static LEFT: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\w{1,16}) left the game$").unwrap());
+static ADVANCEMENT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(\w{1,16}) has (made the advancement|reached the goal|completed the challenge) \[(.*)\]$",
+    )
+    .unwrap()
+});
+static STARTED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"^Done \((\d+(?:\.\d+)?)s\)! For help, type "help""#).unwrap());
+/// A player's name at the start of a message.
+static PLAYER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\w{1,16}) (.*)$").unwrap());
+/// Addresses as the server logs them, e.g. `/127.0.0.1:53412` or `/[0:0:0:0:0:0:0:1]:53412`.
+static ADDRESS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"/(?:\d{1,3}(?:\.\d{1,3}){3}|\[[0-9a-fA-F:%.\w]+\])(?::\d+)?").unwrap()
+});
+
+/// How death messages continue after the player's name. See the `death.` translation keys.
+const DEATHS: &[&str] = &[
+    "was ",
+    "walked into ",
+    "drowned",
+    "died",
+    "experienced kinetic energy",
+    "blew up",
+    "hit the ground too hard",
+    "fell ",
+    "went up in flames",
+    "went off with a bang",
+    "burned to death",
+    "tried to swim in lava",
+    "discovered the floor was lava",
+    "starved to death",
+    "suffocated in a wall",
+    "left the confines of this world",
+    "didn't want to live in the same world as ",
+    "withered away",
+    "froze to death",
+];
+
+#[derive(Clone, Debug)]
+pub struct LogEvent {
+    /// The name of the server whose log this comes from.
+    pub server: Arc<str>,
+    pub kind: LogEventKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogEventKind {
+    Chat {
+        player: String,
+        message: String,
+    },
+    PlayerJoined(String),
+    PlayerLeft(String),
+    /// `message` is the whole death message, player name included.
+    Death {
+        player: String,
+        message: String,
+    },
+    Advancement {
+        player: String,
+        kind: AdvancementKind,
+        title: String,
+    },
+    /// The server finished starting up, which took this many seconds.
+    Started(f64),
+    /// Warnings and errors have addresses redacted, as they often involve connections.
+    Warning(String),
+    Error(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdvancementKind {
+    Task,
+    Goal,
+    Challenge,
+}
+
+/// Parses a line of the log. Lines which aren't interesting (or are the continuation of a
+/// multi-line message, like stack traces) return `None`.
+pub fn parse(line: &str) -> Option<LogEventKind> {
+    let (thread, level, message) = if let Some(captures) = VANILLA_LINE.captures(line) {
+        let thread = group(&captures, 1);
+        (Some(thread), group(&captures, 2), group(&captures, 3))
+    } else {
+        let captures = PAPER_LINE.captures(line)?;
+        (None, group(&captures, 1), group(&captures, 2))
+    };
+
+    match level {
+        "WARN" => return Some(LogEventKind::Warning(redact(message))),
+        "ERROR" | "FATAL" => return Some(LogEventKind::Error(redact(message))),
+        "INFO" => {}
+        _ => return None,
+    }
+
+    if let Some(captures) = CHAT.captures(message) {
+        return Some(LogEventKind::Chat {
+            player: captures[1].into(),
+            message: captures[2].into(),
+        });
+    }
+
+    // everything else comes from the main thread, which keeps messages from other threads (e.g.
+    // from mods) from being taken for deaths
+    if thread.is_some_and(|thread| thread != "Server thread") {
+        return None;
+    }
+
+    if let Some(captures) = JOINED.captures(message) {
+        return Some(LogEventKind::PlayerJoined(captures[1].into()));
+    }
+    if let Some(captures) = LEFT.captures(message) {
+        return Some(LogEventKind::PlayerLeft(captures[1].into()));
+    }
+    if let Some(captures) = ADVANCEMENT.captures(message) {
+        let kind = match &captures[2] {
+            "reached the goal" => AdvancementKind::Goal,
+            "completed the challenge" => AdvancementKind::Challenge,
+            _ => AdvancementKind::Task,
+        };
+        return Some(LogEventKind::Advancement {
+            player: captures[1].into(),
+            kind,
+            title: captures[3].into(),
+        });
+    }
+    if let Some(captures) = STARTED.captures(message) {
+        return Some(LogEventKind::Started(captures[1].parse().ok()?));
+    }
+    if let Some(captures) = PLAYER.captures(message) {
+        if DEATHS.iter().any(|death| captures[2].starts_with(death)) {
+            return Some(LogEventKind::Death {
+                player: captures[1].into(),
+                message: message.into(),
+            });
+        }
+    }
+
+    None
+}
+
+/// Returns a group which always participates in the match.
+fn group<'a>(captures: &regex::Captures<'a>, index: usize) -> &'a str {
+    captures.get(index).unwrap().as_str()
+}
+
+fn redact(message: &str) -> String {
+    ADDRESS.replace_all(message, "/<redacted>").into_owned()
+}
+
+/// Where the followed file was read up to.
+#[derive(Clone, Copy)]
+struct Position {
+    /// Identifies the file, so that a new one can be told apart from the old one. `None` if the
+    /// log didn't exist yet, in which case the next one is read from the start.
+    file: Option<u64>,
+    offset: u64,
+}
+
+/// Reads the lines appended to `latest.log` since the last call. When the server rotates the log
+/// (at startup and at midnight), the rest of the old one is read from its gzipped archive.
+pub struct LogFollower {
+    directory: PathBuf,
+    position: Option<Position>,
+    /// The start of a line which hasn't been completely written yet.
+    partial: Vec<u8>,
+}
+
+impl LogFollower {
+    /// Follows the log in the `logs` `directory`, starting at its current end.
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            position: None,
+            partial: vec![],
+        }
+    }
+
+    pub fn poll(&mut self) -> io::Result<Vec<String>> {
+        let path = self.directory.join("latest.log");
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => {
+                self.position.get_or_insert(Position {
+                    file: None,
+                    offset: 0,
+                });
+                return Ok(vec![]);
+            }
+            Err(why) => return Err(why),
+        };
+        let file = file_id(&metadata);
+
+        let mut lines = vec![];
+        let position = match self.position {
+            None => Position {
+                file: Some(file),
+                offset: metadata.len(),
+            },
+            Some(position)
+                if position.file.is_some_and(|old| old != file)
+                    || metadata.len() < position.offset =>
+            {
+                if let Some(archive) = latest_archive(&self.directory)? {
+                    let mut archive = GzDecoder::new(File::open(archive)?);
+                    io::copy(&mut (&mut archive).take(position.offset), &mut io::sink())?;
+                    self.read(archive, &mut lines)?;
+                }
+                // the old log is done, even if its last line was cut off
+                if !self.partial.is_empty() {
+                    lines.push(decode(&std::mem::take(&mut self.partial)));
+                }
+                Position {
+                    file: Some(file),
+                    offset: 0,
+                }
+            }
+            Some(position) => position,
+        };
+
+        let mut log = File::open(&path)?;
+        log.seek(SeekFrom::Start(position.offset))?;
+        let read = self.read(log, &mut lines)?;
+        self.position = Some(Position {
+            file: Some(file),
+            offset: position.offset + read,
+        });
+
+        Ok(lines)
+    }
+
+    /// Reads everything from `reader`, adding the complete lines to `lines`. Returns how many bytes
+    /// were read.
+    fn read(&mut self, mut reader: impl Read, lines: &mut Vec<String>) -> io::Result<u64> {
+        let mut buffer = vec![];
+        let read = reader.read_to_end(&mut buffer)?;
+
+        let mut rest = &buffer[..];
+        while let Some(end) = rest.iter().position(|&byte| byte == b'\n') {
+            self.partial.extend_from_slice(&rest[..end]);
+            lines.push(decode(&std::mem::take(&mut self.partial)));
+            rest = &rest[end + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+
+        Ok(read as u64)
+    }
+}
+
+fn decode(line: &[u8]) -> String {
+    let line = String::from_utf8_lossy(line);
+    line.strip_suffix('\r').unwrap_or(&line).to_owned()
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+/// Without inodes, rotations are only noticed by the log getting shorter.
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> u64 {
+    0
+}
+
+/// Returns the most recently modified `.log.gz` file in `directory`.
+fn latest_archive(directory: &Path) -> io::Result<Option<PathBuf>> {
+    let mut latest = None;
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().ends_with(".log.gz") {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if latest.as_ref().is_none_or(|(latest, _)| modified > *latest) {
+            latest = Some((modified, entry.path()));
+        }
+    }
+    Ok(latest.map(|(_, path)| path))
+}
+
+/// Follows the log of the server with the given name and publishes its events, until the server
+/// is removed from the config.
+pub async fn tail(data: Data, server_name: Box<str>) {
+    let server_name: Arc<str> = Arc::from(server_name);
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut follower: Option<LogFollower> = None;
+
+    loop {
+        interval.tick().await;
+
+        let settings = data.settings();
+        let Some(server) = settings.server(&server_name) else {
+            return;
+        };
+        let directory = Path::new(&*server.directory).join("logs");
+
+        let mut current = match follower.take() {
+            Some(follower) if follower.directory == directory => follower,
+            _ => LogFollower::new(directory),
+        };
+        let (current, lines) = tokio::task::spawn_blocking(move || {
+            let lines = current.poll();
+            (current, lines)
+        })
+        .await
+        .unwrap();
+        follower = Some(current);
+
+        let lines = match lines {
+            Ok(lines) => lines,
+            Err(why) => {
+                log::warn!("Couldn't read the log of {server_name}: {why}");
+                continue;
+            }
+        };
+        for kind in lines.iter().filter_map(|line| parse(line)) {
+            // this only fails if there are no subscribers, which is fine
+            let _ = data.log_events.send(LogEvent {
+                server: Arc::clone(&server_name),
+                kind,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn info(message: &str) -> Option<LogEventKind> {
+        parse(&format!("[12:34:56] [Server thread/INFO]: {message}"))
+    }
+
+    #[test]
+    fn events() {
+        assert_eq!(
+            info("<alex> hello <there>"),
+            Some(LogEventKind::Chat {
+                player: "alex".into(),
+                message: "hello <there>".into()
+            })
+        );
+        assert_eq!(
+            parse("[12:34:56 INFO]: [Not Secure] <alex> hi"),
+            Some(LogEventKind::Chat {
+                player: "alex".into(),
+                message: "hi".into()
+            })
+        );
+        assert_eq!(
+            info("alex joined the game"),
+            Some(LogEventKind::PlayerJoined("alex".into()))
+        );
+        assert_eq!(
+            info("alex left the game"),
+            Some(LogEventKind::PlayerLeft("alex".into()))
+        );
+        assert_eq!(
+            info("alex was slain by Zombie"),
+            Some(LogEventKind::Death {
+                player: "alex".into(),
+                message: "alex was slain by Zombie".into()
+            })
+        );
+        assert_eq!(
+            parse("[12:34:56 INFO]: steve drowned"),
+            Some(LogEventKind::Death {
+                player: "steve".into(),
+                message: "steve drowned".into()
+            })
+        );
+        assert_eq!(
+            info("alex has completed the challenge [Monster Hunter]"),
+            Some(LogEventKind::Advancement {
+                player: "alex".into(),
+                kind: AdvancementKind::Challenge,
+                title: "Monster Hunter".into()
+            })
+        );
+        assert_eq!(
+            info(r#"Done (12.345s)! For help, type "help""#),
+            Some(LogEventKind::Started(12.345))
+        );
+
+        assert_eq!(
+            info("alex[/127.0.0.1:53412] logged in with entity id 1"),
+            None
+        );
+        assert_eq!(info("Preparing spawn area: 50%"), None);
+        assert_eq!(
+            parse("[12:34:56] [Worker-Main-1/INFO]: alex was here"),
+            None
+        );
+        assert_eq!(parse("\tat java.base/java.lang.Thread.run"), None);
+    }
+
+    #[test]
+    fn addresses_are_redacted() {
+        assert_eq!(
+            parse("[12:34:56] [Server thread/WARN]: alex (/192.168.1.20:53412) lost connection"),
+            Some(LogEventKind::Warning(
+                "alex (/<redacted>) lost connection".into()
+            ))
+        );
+        assert_eq!(
+            parse(
+                "[12:34:56] [Netty Epoll Server IO #1/ERROR]: Error from /[0:0:0:0:0:0:0:1]:1234"
+            ),
+            Some(LogEventKind::Error("Error from /<redacted>".into()))
+        );
+    }
+
+    #[test]
+    fn rotation() {
+        let directory =
+            std::env::temp_dir().join(format!("ferrisquery-logs-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let latest = directory.join("latest.log");
+        let append = |text: &str| {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&latest)
+                .unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+
+        append("old\n");
+        let mut follower = LogFollower::new(directory.clone());
+        assert!(follower.poll().unwrap().is_empty());
+
+        append("a\nb");
+        assert_eq!(follower.poll().unwrap(), ["a"]);
+        append("c\r\nd\n");
+        assert_eq!(follower.poll().unwrap(), ["bc", "d"]);
+
+        // the server archives the log before it's completely read
+        append("e\nf\n");
+        let mut archive = GzEncoder::new(
+            File::create(directory.join("2024-01-01-1.log.gz")).unwrap(),
+            Compression::default(),
+        );
+        archive.write_all(&std::fs::read(&latest).unwrap()).unwrap();
+        archive.finish().unwrap();
+        std::fs::remove_file(&latest).unwrap();
+        append("g\n");
+        assert_eq!(follower.poll().unwrap(), ["e", "f", "g"]);
+        assert!(follower.poll().unwrap().is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
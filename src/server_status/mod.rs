@@ -0,0 +1,242 @@
+mod query;
+mod slp;
+
+use std::borrow::Cow;
+use std::time::Duration;
+
+use serde::Deserialize;
+use uuid_mc::PlayerUuid;
+
+use crate::config::StatusSource;
+use crate::interface::Interface;
+use crate::server::Server;
+
+/// How long to wait for a response when querying the server directly (i.e. not using RCON).
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+pub struct PlayerData {
+    pub name: String,
+    pub nickname: Option<String>,
+    pub uuid: Option<PlayerUuid>,
+}
+
+#[derive(Deserialize)]
+pub struct OnlineServerStatus {
+    pub current_players: i32,
+    pub max_players: i32,
+    pub list: Vec<PlayerData>,
+    pub tps: Option<[f32; 5]>,
+    /// The average milliseconds per tick over the last minute, if the server reports it.
+    #[serde(default)]
+    pub mspt: Option<f32>,
+    /// The message of the day, as shown in the multiplayer screen.
+    #[serde(default)]
+    pub motd: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub protocol: Option<i32>,
+    /// The name of the main world.
+    #[serde(default)]
+    pub map: Option<String>,
+    /// The game type, which is always "SMP" for vanilla servers.
+    #[serde(default)]
+    pub game_type: Option<String>,
+    #[serde(default)]
+    pub plugins: Option<Vec<String>>,
+}
+
+#[allow(clippy::large_enum_variant)] // only a handful of these exist at any given moment
+pub enum ServerStatus {
+    Offline,
+    Online(OnlineServerStatus),
+}
+
+/// Gets the status of the server by trying each of its status sources in order, until one of them
+/// reports that the server is online.
+pub async fn get_server_status(server: &Server) -> Result<ServerStatus, Cow<'static, str>> {
+    let mut error = None;
+
+    for source in server.status_sources.iter() {
+        let status = match source {
+            StatusSource::Rcon => {
+                rcon_status(&mut *server.interface.lock().await, server.has_list_json).await
+            }
+            StatusSource::Slp => slp_status(&server.slp_address).await,
+            StatusSource::Query => query_status(&server.query_address).await,
+        };
+
+        match status {
+            Ok(ServerStatus::Offline) => {}
+            Ok(status) => return Ok(status),
+            Err(why) => {
+                error.get_or_insert(why);
+            }
+        }
+    }
+
+    match error {
+        Some(why) => Err(why),
+        None => Ok(ServerStatus::Offline),
+    }
+}
+
+async fn slp_status(address: &str) -> Result<ServerStatus, Cow<'static, str>> {
+    match tokio::time::timeout(QUERY_TIMEOUT, slp::get_status(address)).await {
+        Ok(Ok(status)) => Ok(ServerStatus::Online(status)),
+        Ok(Err(why)) if why.kind() == std::io::ErrorKind::InvalidData => {
+            eprintln!("Invalid SLP response from {address}: {why}");
+            Err("Invalid Server List Ping response".into())
+        }
+        // the server can't be reached, so it must be offline.
+        Ok(Err(_)) | Err(_) => Ok(ServerStatus::Offline),
+    }
+}
+
+async fn query_status(address: &str) -> Result<ServerStatus, Cow<'static, str>> {
+    match tokio::time::timeout(QUERY_TIMEOUT, query::get_status(address)).await {
+        Ok(Ok(status)) => Ok(ServerStatus::Online(status)),
+        Ok(Err(why)) if why.kind() == std::io::ErrorKind::InvalidData => {
+            eprintln!("Invalid query response from {address}: {why}");
+            Err("Invalid query response".into())
+        }
+        // the server can't be reached (UDP gives no indication of this other than a
+        // timeout), so it must be offline.
+        Ok(Err(_)) | Err(_) => Ok(ServerStatus::Offline),
+    }
+}
+
+async fn rcon_status(
+    interface: &mut Interface,
+    uses_list_json: bool,
+) -> Result<ServerStatus, Cow<'static, str>> {
+    if uses_list_json {
+        let list = interface.exec("list json").await;
+        if let Ok(list) = list {
+            let status = serde_json::from_str::<OnlineServerStatus>(&list);
+            match status {
+                Ok(status) => Ok(ServerStatus::Online(status)),
+                Err(why) => {
+                    eprintln!("Deserialization error: {why}. Response from server: {list}");
+                    Err("Deserialization error (this is a bug)".into())
+                }
+            }
+        } else {
+            // if there's an error, it can't be a CommandTooLong. therefore, the server must be offline.
+            Ok(ServerStatus::Offline)
+        }
+    } else {
+        let regex = &*super::LIST_REGEX;
+        let list = interface.exec("list").await;
+        if let Ok(list) = list {
+            let list = list.trim();
+            let Some(captures) = regex.captures(list) else {
+                eprintln!("Regex error. Response from server: {list}");
+                return Err("Regex error (this is a bug)".into());
+            };
+
+            let mut captures = captures.iter().flatten().map(|mat| mat.as_str());
+            captures.next().unwrap(); // this is the entire text, not interesting
+
+            // these unwraps are ok, because we know we're getting a well-formatted response from the server
+            let current_players: i32 = captures.next().unwrap().parse().unwrap();
+            let max_players: i32 = captures.next().unwrap().parse().unwrap();
+
+            // an empty server has nothing after the colon, which would split into a single ""
+            let mut players: Vec<&str> = captures
+                .next()
+                .unwrap_or_default()
+                .split(", ")
+                .filter(|name| !name.is_empty())
+                .collect();
+            players.sort_unstable();
+
+            Ok(ServerStatus::Online(OnlineServerStatus {
+                current_players,
+                max_players,
+                list: players
+                    .into_iter()
+                    .map(|name| PlayerData {
+                        name: name.to_string(),
+                        nickname: None,
+                        uuid: None,
+                    })
+                    .collect(),
+                tps: None,
+                mspt: None,
+                motd: None,
+                version: None,
+                protocol: None,
+                map: None,
+                game_type: None,
+                plugins: None,
+            }))
+        } else {
+            // if there's an error, it can't be a CommandTooLong. therefore, the server must be offline.
+            Ok(ServerStatus::Offline)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rcon::MockRcon;
+
+    fn online(status: Result<ServerStatus, Cow<'static, str>>) -> OnlineServerStatus {
+        match status {
+            Ok(ServerStatus::Online(status)) => status,
+            Ok(ServerStatus::Offline) => panic!("the server is offline"),
+            Err(why) => panic!("{why}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn list_json() {
+        let rcon = MockRcon::start("hunter2").await;
+        rcon.respond(
+            "list json",
+            r#"{"current_players": 1, "max_players": 20, "tps": null,
+                "list": [{"name": "alex", "nickname": "<red>Alex", "uuid": null}]}"#,
+        );
+        let server = rcon.server(true, "");
+
+        let status = online(get_server_status(&server).await);
+        assert_eq!((status.current_players, status.max_players), (1, 20));
+        assert_eq!(status.list[0].name, "alex");
+        assert_eq!(status.list[0].nickname.as_deref(), Some("<red>Alex"));
+
+        rcon.respond("list json", "Unknown or incomplete command");
+        assert!(get_server_status(&server).await.is_err());
+
+        drop(rcon);
+        assert!(matches!(
+            get_server_status(&server).await,
+            Ok(ServerStatus::Offline)
+        ));
+    }
+
+    #[tokio::test]
+    async fn list_regex() {
+        let rcon = MockRcon::start("hunter2").await;
+        rcon.respond(
+            "list",
+            "There are 2 of a max of 20 players online: steve, alex",
+        );
+        let server = rcon.server(false, "");
+
+        let status = online(get_server_status(&server).await);
+        assert_eq!((status.current_players, status.max_players), (2, 20));
+        let names: Vec<_> = status.list.iter().map(|player| &*player.name).collect();
+        assert_eq!(names, ["alex", "steve"]);
+
+        rcon.respond("list", "There are 0 of a max of 20 players online:");
+        let status = online(get_server_status(&server).await);
+        assert_eq!(status.current_players, 0);
+        assert!(status.list.is_empty());
+
+        rcon.respond("list", "Unknown or incomplete command");
+        assert!(get_server_status(&server).await.is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! A client for the GameSpy4 query protocol, which is enabled using `enable-query` in
+//! `server.properties`. See <https://wiki.vg/Query>.
+
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+
+use tokio::net::{lookup_host, UdpSocket};
+
+use super::{OnlineServerStatus, PlayerData};
+
+/// The port used when the address doesn't specify one. This is the same as the game's port,
+/// since that's what `query.port` defaults to.
+const DEFAULT_PORT: u16 = 25565;
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+
+/// The constant padding which precedes the key-value section of a full stat response.
+const KV_PADDING: &[u8] = b"splitnum\0\x80\0";
+/// The constant padding which precedes the player section of a full stat response.
+const PLAYER_PADDING: &[u8] = b"\x01player_\0\0";
+
+/// Only the lower 4 bits of each byte of the session id are used by the server.
+const SESSION_ID_MASK: i32 = 0x0f0f0f0f;
+
+/// Queries the full stat of the server at `address`. Protocol violations are reported using
+/// [`ErrorKind::InvalidData`], while any other error means the server couldn't be reached.
+pub async fn get_status(address: &str) -> io::Result<OnlineServerStatus> {
+    let target = lookup_host(with_default_port(address))
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "couldn't resolve the address"))?;
+
+    let local = if target.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(target).await?;
+
+    let session_id = std::process::id() as i32 & SESSION_ID_MASK;
+    let mut buf = vec![0; u16::MAX as usize];
+
+    // handshake
+    socket
+        .send(&request(TYPE_HANDSHAKE, session_id, &[]))
+        .await?;
+    let len = socket.recv(&mut buf).await?;
+    let payload = response_payload(&buf[..len], TYPE_HANDSHAKE, session_id)?;
+    let challenge: i32 = read_string(&mut &payload[..])?
+        .parse()
+        .map_err(|_| invalid_data("invalid challenge token"))?;
+
+    // full stat, which is requested by padding the request with 4 bytes
+    let mut body = challenge.to_be_bytes().to_vec();
+    body.extend_from_slice(&[0; 4]);
+    socket.send(&request(TYPE_STAT, session_id, &body)).await?;
+    let len = socket.recv(&mut buf).await?;
+    let payload = response_payload(&buf[..len], TYPE_STAT, session_id)?;
+
+    parse_full_stat(payload)
+}
+
+fn request(kind: u8, session_id: i32, body: &[u8]) -> Vec<u8> {
+    let mut request = MAGIC.to_vec();
+    request.push(kind);
+    request.extend_from_slice(&session_id.to_be_bytes());
+    request.extend_from_slice(body);
+    request
+}
+
+/// Checks the header of a response, and returns what comes after it.
+fn response_payload(response: &[u8], kind: u8, session_id: i32) -> io::Result<&[u8]> {
+    if response.len() < 5 || response[0] != kind {
+        return Err(invalid_data("unexpected response type"));
+    }
+    if response[1..5] != session_id.to_be_bytes() {
+        return Err(invalid_data("session id mismatch"));
+    }
+    Ok(&response[5..])
+}
+
+fn parse_full_stat(mut payload: &[u8]) -> io::Result<OnlineServerStatus> {
+    payload = payload
+        .strip_prefix(KV_PADDING)
+        .ok_or_else(|| invalid_data("missing key-value padding"))?;
+
+    let mut status = OnlineServerStatus {
+        current_players: 0,
+        max_players: 0,
+        list: vec![],
+        tps: None,
+        mspt: None,
+        motd: None,
+        version: None,
+        protocol: None,
+        map: None,
+        game_type: None,
+        plugins: None,
+    };
+
+    loop {
+        let key = read_string(&mut payload)?;
+        if key.is_empty() {
+            break;
+        }
+        let value = read_string(&mut payload)?;
+
+        match &*key {
+            "hostname" => status.motd = Some(value),
+            "gametype" => status.game_type = Some(value),
+            "version" => status.version = Some(value),
+            "plugins" => status.plugins = Some(parse_plugins(&value)),
+            "map" => status.map = Some(value),
+            "numplayers" => {
+                status.current_players = value.parse().map_err(|_| invalid_data("numplayers"))?
+            }
+            "maxplayers" => {
+                status.max_players = value.parse().map_err(|_| invalid_data("maxplayers"))?
+            }
+            _ => {}
+        }
+    }
+
+    payload = payload
+        .strip_prefix(PLAYER_PADDING)
+        .ok_or_else(|| invalid_data("missing player padding"))?;
+
+    loop {
+        let name = read_string(&mut payload)?;
+        if name.is_empty() {
+            break;
+        }
+        status.list.push(PlayerData {
+            name,
+            nickname: None,
+            uuid: None,
+        });
+    }
+    status.list.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(status)
+}
+
+/// Plugins are reported as `"<server mod>: <plugin>; <plugin>; ..."`, or as just the server mod
+/// (or nothing at all) if there are no plugins.
+fn parse_plugins(plugins: &str) -> Vec<String> {
+    let Some((_, plugins)) = plugins.split_once(": ") else {
+        return vec![];
+    };
+
+    plugins
+        .split("; ")
+        .map(str::trim)
+        .filter(|plugin| !plugin.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Reads a null-terminated string. The protocol uses ISO 8859-1, which maps directly to the
+/// first 256 unicode code points.
+fn read_string(payload: &mut &[u8]) -> io::Result<String> {
+    let end = payload
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid_data("unterminated string"))?;
+    let string = payload[..end].iter().map(|&byte| byte as char).collect();
+    *payload = &payload[end + 1..];
+    Ok(string)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_owned())
+}
+
+/// Adds the default port to `address` if it doesn't have one. IPv6 addresses need brackets
+/// (`[::1]:25565`) to be given a port, but they can be left out without one.
+fn with_default_port(address: &str) -> String {
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return SocketAddr::new(ip, DEFAULT_PORT).to_string();
+    }
+
+    let has_port = match address.rsplit_once(':') {
+        Some((host, _)) => !host.starts_with('[') || host.ends_with(']'),
+        None => false,
+    };
+    if has_port {
+        address.to_owned()
+    } else {
+        format!("{address}:{DEFAULT_PORT}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    const CHALLENGE: i32 = 9513307;
+
+    /// A minimal query server, which answers `requests` requests and then stops.
+    async fn fake_server(players: &'static [&'static str], requests: usize) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            for _ in 0..requests {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let request = &buf[..len];
+                assert_eq!(request[..2], MAGIC);
+                let kind = request[2];
+                let session_id = &request[3..7];
+
+                let mut response = vec![kind];
+                response.extend_from_slice(session_id);
+
+                if kind == TYPE_HANDSHAKE {
+                    response.extend_from_slice(CHALLENGE.to_string().as_bytes());
+                    response.push(0);
+                } else {
+                    assert_eq!(request[7..11], CHALLENGE.to_be_bytes());
+                    assert_eq!(len, 15, "expected a full stat request");
+
+                    response.extend_from_slice(KV_PADDING);
+                    for (key, value) in [
+                        ("hostname", "A \u{a7}aMinecraft\u{a7}r Server"),
+                        ("gametype", "SMP"),
+                        ("game_id", "MINECRAFT"),
+                        ("version", "1.20.4"),
+                        (
+                            "plugins",
+                            "CraftBukkit on Bukkit 1.20.4: WorldEdit 7.2; Essentials 2.20",
+                        ),
+                        ("map", "world"),
+                        ("numplayers", &players.len().to_string()),
+                        ("maxplayers", "20"),
+                        ("hostport", "25565"),
+                        ("hostip", "127.0.0.1"),
+                    ] {
+                        // the protocol uses ISO 8859-1
+                        response.extend(key.chars().map(|c| c as u8));
+                        response.push(0);
+                        response.extend(value.chars().map(|c| c as u8));
+                        response.push(0);
+                    }
+                    response.push(0);
+
+                    response.extend_from_slice(PLAYER_PADDING);
+                    for player in players {
+                        response.extend_from_slice(player.as_bytes());
+                        response.push(0);
+                    }
+                    response.push(0);
+                }
+
+                socket.send_to(&response, peer).await.unwrap();
+            }
+        });
+
+        address
+    }
+
+    #[tokio::test]
+    async fn full_stat() {
+        let address = fake_server(&["steve", "alex", "notch"], 2).await;
+        let status = get_status(&address.to_string()).await.unwrap();
+
+        assert_eq!(status.current_players, 3);
+        assert_eq!(status.max_players, 20);
+        assert_eq!(
+            status.motd.as_deref(),
+            Some("A \u{a7}aMinecraft\u{a7}r Server")
+        );
+        assert_eq!(status.version.as_deref(), Some("1.20.4"));
+        assert_eq!(status.game_type.as_deref(), Some("SMP"));
+        assert_eq!(status.map.as_deref(), Some("world"));
+        assert_eq!(
+            status.plugins,
+            Some(vec![
+                "WorldEdit 7.2".to_owned(),
+                "Essentials 2.20".to_owned()
+            ])
+        );
+
+        let names: Vec<&str> = status.list.iter().map(|p| &*p.name).collect();
+        assert_eq!(names, ["alex", "notch", "steve"]);
+    }
+
+    #[tokio::test]
+    async fn empty_server() {
+        let address = fake_server(&[], 2).await;
+        let status = get_status(&address.to_string()).await.unwrap();
+
+        assert_eq!(status.current_players, 0);
+        assert!(status.list.is_empty());
+    }
+
+    #[test]
+    fn plugins_without_plugins() {
+        assert!(parse_plugins("").is_empty());
+        assert!(parse_plugins("CraftBukkit on Bukkit 1.20.4").is_empty());
+    }
+
+    #[test]
+    fn truncated_response() {
+        let mut payload = KV_PADDING.to_vec();
+        payload.extend_from_slice(b"hostname\0A Minecraft Server");
+
+        let Err(error) = parse_full_stat(&payload) else {
+            panic!("a truncated response was parsed successfully");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn default_port() {
+        assert_eq!(with_default_port("localhost"), "localhost:25565");
+        assert_eq!(with_default_port("localhost:25566"), "localhost:25566");
+        assert_eq!(with_default_port("127.0.0.1"), "127.0.0.1:25565");
+        assert_eq!(with_default_port("::1"), "[::1]:25565");
+        assert_eq!(with_default_port("[::1]"), "[::1]:25565");
+        assert_eq!(with_default_port("[::1]:25566"), "[::1]:25566");
+    }
+
+    #[test]
+    fn session_id_mismatch() {
+        let response = [TYPE_HANDSHAKE, 0, 0, 0, 1, b'1', 0];
+
+        assert!(response_payload(&response, TYPE_HANDSHAKE, 1).is_ok());
+        assert!(response_payload(&response, TYPE_HANDSHAKE, 2).is_err());
+        assert!(response_payload(&response, TYPE_STAT, 1).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! A client for the Server List Ping protocol, which is what the multiplayer screen uses to show
+//! a server's status. See <https://wiki.vg/Server_List_Ping>.
+
+use std::io::{self, ErrorKind};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use uuid_mc::{PlayerUuid, Uuid};
+
+use super::{OnlineServerStatus, PlayerData};
+
+/// The port used when the address doesn't specify one.
+const DEFAULT_PORT: u16 = 25565;
+
+/// By convention, -1 is sent when the client doesn't know which version the server runs.
+const PROTOCOL_VERSION: i32 = -1;
+
+/// The largest response we're willing to read. Favicons make up most of the response, and are
+/// limited in size by the server, so this is plenty.
+const MAX_PACKET_LENGTH: i32 = 1 << 21;
+
+#[derive(Deserialize)]
+struct Response {
+    version: Option<Version>,
+    players: Option<Players>,
+    description: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    name: String,
+    protocol: i32,
+}
+
+#[derive(Deserialize)]
+struct Players {
+    max: i32,
+    online: i32,
+    #[serde(default)]
+    sample: Vec<SamplePlayer>,
+}
+
+#[derive(Deserialize)]
+struct SamplePlayer {
+    name: String,
+    id: String,
+}
+
+/// Queries the status of the server at `address`. Protocol violations are reported using
+/// [`ErrorKind::InvalidData`], while any other error means the server couldn't be reached.
+pub async fn get_status(address: &str) -> io::Result<OnlineServerStatus> {
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "invalid port"))?,
+        ),
+        None => (address, DEFAULT_PORT),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let mut stream = TcpStream::connect((host, port)).await?;
+
+    // handshake, with the next state set to 1 (status)
+    let mut handshake = vec![];
+    write_varint(&mut handshake, 0x00);
+    write_varint(&mut handshake, PROTOCOL_VERSION);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, 1);
+    send_packet(&mut stream, &handshake).await?;
+
+    // status request
+    send_packet(&mut stream, &[0x00]).await?;
+
+    let length = read_varint(&mut stream).await?;
+    if !(1..=MAX_PACKET_LENGTH).contains(&length) {
+        return Err(invalid_data("invalid packet length"));
+    }
+    let mut packet = vec![0; length as usize];
+    stream.read_exact(&mut packet).await?;
+
+    let mut packet = &packet[..];
+    if read_varint(&mut packet).await? != 0x00 {
+        return Err(invalid_data("unexpected packet id"));
+    }
+    let json_length = read_varint(&mut packet).await?;
+    if json_length < 0 || json_length as usize > packet.len() {
+        return Err(invalid_data("invalid string length"));
+    }
+    let json = std::str::from_utf8(&packet[..json_length as usize])
+        .map_err(|_| invalid_data("the response isn't valid UTF-8"))?;
+
+    let response: Response =
+        serde_json::from_str(json).map_err(|why| invalid_data(&why.to_string()))?;
+
+    Ok(response.into())
+}
+
+impl From<Response> for OnlineServerStatus {
+    fn from(response: Response) -> Self {
+        let players = response.players.unwrap_or(Players {
+            max: 0,
+            online: 0,
+            sample: vec![],
+        });
+
+        let mut list: Vec<PlayerData> = players
+            .sample
+            .into_iter()
+            .map(|player| PlayerData {
+                uuid: Uuid::try_parse(&player.id)
+                    .ok()
+                    .and_then(|uuid| PlayerUuid::new_with_uuid(uuid).ok()),
+                name: player.name,
+                nickname: None,
+            })
+            .collect();
+        list.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        OnlineServerStatus {
+            current_players: players.online,
+            max_players: players.max,
+            list,
+            tps: None,
+            mspt: None,
+            motd: response.description.as_ref().map(flatten_text_component),
+            version: response
+                .version
+                .as_ref()
+                .map(|version| version.name.clone()),
+            protocol: response.version.map(|version| version.protocol),
+            map: None,
+            game_type: None,
+            plugins: None,
+        }
+    }
+}
+
+/// Converts a JSON text component into the plain text it represents.
+fn flatten_text_component(component: &Value) -> String {
+    fn flatten(component: &Value, output: &mut String) {
+        match component {
+            Value::String(text) => output.push_str(text),
+            Value::Array(components) => components.iter().for_each(|c| flatten(c, output)),
+            Value::Object(object) => {
+                if let Some(text) = object.get("text").and_then(Value::as_str) {
+                    output.push_str(text);
+                }
+                if let Some(extra) = object.get("extra") {
+                    flatten(extra, output);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut output = String::new();
+    flatten(component, &mut output);
+    output
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_owned())
+}
+
+async fn send_packet(stream: &mut TcpStream, packet: &[u8]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(packet.len() + 5);
+    write_varint(&mut buf, packet.len() as i32);
+    buf.extend_from_slice(packet);
+    stream.write_all(&buf).await
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7f == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, string: &str) {
+    write_varint(buf, string.len() as i32);
+    buf.extend_from_slice(string.as_bytes());
+}
+
+async fn read_varint(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<i32> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let byte = reader.read_u8().await?;
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+
+    Err(invalid_data("VarInt is too big"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// A minimal server, which checks the handshake and status request of a single client and
+    /// answers with `response` as the whole packet (without its length).
+    async fn fake_server(response: Vec<u8>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let length = read_varint(&mut stream).await.unwrap();
+            let mut handshake = vec![0; length as usize];
+            stream.read_exact(&mut handshake).await.unwrap();
+            let mut handshake = &handshake[..];
+            assert_eq!(read_varint(&mut handshake).await.unwrap(), 0x00);
+            assert_eq!(read_varint(&mut handshake).await.unwrap(), PROTOCOL_VERSION);
+            let host_length = read_varint(&mut handshake).await.unwrap() as usize;
+            assert_eq!(&handshake[..host_length], b"127.0.0.1");
+            let port = u16::from_be_bytes([handshake[host_length], handshake[host_length + 1]]);
+            assert_eq!(port, address.port());
+            assert_eq!(handshake[host_length + 2..], [1]);
+
+            let mut request = [0; 2];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [1, 0x00]);
+
+            let mut packet = vec![];
+            write_varint(&mut packet, response.len() as i32);
+            packet.extend_from_slice(&response);
+            stream.write_all(&packet).await.unwrap();
+        });
+
+        address
+    }
+
+    fn status_response(json: &str) -> Vec<u8> {
+        let mut response = vec![];
+        write_varint(&mut response, 0x00);
+        write_string(&mut response, json);
+        response
+    }
+
+    #[tokio::test]
+    async fn varints() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (25565, &[0xdd, 0xc7, 0x01]),
+            (i32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+            (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ] {
+            let mut buf = vec![];
+            write_varint(&mut buf, value);
+            assert_eq!(buf, encoded);
+            assert_eq!(read_varint(&mut &buf[..]).await.unwrap(), value);
+        }
+
+        let error = read_varint(&mut &[0xff; 6][..]).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn status() {
+        // the favicon makes the packet long enough for its length to take several bytes
+        let favicon = format!("data:image/png;base64,{}", "A".repeat(300));
+        let json = serde_json::json!({
+            "version": { "name": "1.20.4", "protocol": 765 },
+            "players": {
+                "max": 20,
+                "online": 5,
+                "sample": [
+                    { "name": "steve", "id": "4566e69f-c907-48ee-8d71-d7ba5aa00d20" },
+                    { "name": "alex", "id": "not a uuid" },
+                ],
+            },
+            "description": { "text": "A ", "extra": [{ "text": "Minecraft", "color": "green" }, " Server"] },
+            "favicon": favicon,
+        });
+        let address = fake_server(status_response(&json.to_string())).await;
+
+        let status = get_status(&address.to_string()).await.unwrap();
+        assert_eq!((status.current_players, status.max_players), (5, 20));
+        assert_eq!(status.motd.as_deref(), Some("A Minecraft Server"));
+        assert_eq!(status.version.as_deref(), Some("1.20.4"));
+        assert_eq!(status.protocol, Some(765));
+
+        let names: Vec<&str> = status.list.iter().map(|p| &*p.name).collect();
+        assert_eq!(names, ["alex", "steve"]);
+        assert!(status.list[0].uuid.is_none());
+        assert!(status.list[1].uuid.is_some());
+    }
+
+    #[tokio::test]
+    async fn malformed_responses() {
+        let mut wrong_id = vec![];
+        write_varint(&mut wrong_id, 0x01);
+        write_string(&mut wrong_id, "{}");
+
+        let mut too_long = vec![];
+        write_varint(&mut too_long, 0x00);
+        write_varint(&mut too_long, 100);
+        too_long.extend_from_slice(b"{}");
+
+        for response in [wrong_id, too_long, status_response("not json")] {
+            let address = fake_server(response).await.to_string();
+            let Err(error) = get_status(&address).await else {
+                panic!("a malformed response was parsed successfully");
+            };
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+
+        // which is reported as an error, rather than the server being offline
+        let address = fake_server(status_response("not json")).await.to_string();
+        assert!(super::super::slp_status(&address).await.is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude::{ChannelId, RoleId};
+
+use crate::config::{
+    AlertsConfig, Config, ListConfig, ModerationConfig, PermissionsConfig, RunConfig,
+};
+use crate::database_api::MonadApi;
+use crate::server::Server;
+
+/// The part of the bot's state that comes from the config. It is replaced as a whole whenever the
+/// config is reloaded, so anything that reads from it sees either the old or the new config.
+pub struct Settings {
+    pub op_role_id: RoleId,
+    pub moderation: ModerationConfig,
+    pub list: ListConfig,
+    pub alerts: Option<AlertsConfig>,
+    pub audit_channel_id: Option<ChannelId>,
+    pub run: RunConfig,
+    pub permissions: PermissionsConfig,
+    pub servers: Vec<Arc<Server>>,
+    pub db_api: Option<Arc<MonadApi>>,
+}
+
+impl Settings {
+    /// Creates the settings for `config`, carrying over the state of any servers from `previous`.
+    pub async fn new(config: &Config, previous: Option<&Settings>) -> Self {
+        let mut servers = Vec::with_capacity(config.servers.len());
+        for server_config in &config.servers {
+            let previous = previous.and_then(|previous| previous.server(&server_config.name));
+            servers.push(Arc::new(
+                Server::new(server_config, previous.map(Arc::as_ref)).await,
+            ));
+        }
+
+        let db_api = config.database.as_ref().map(|database| {
+            Arc::new(MonadApi::new(
+                &database.username,
+                &database.admin_endpoint,
+                &database.admin_password,
+                &database.user_endpoint,
+                &database.user_password,
+            ))
+        });
+
+        Self {
+            op_role_id: RoleId::new(config.discord.op_role_id),
+            moderation: config.moderation.clone(),
+            list: config.list.clone(),
+            alerts: config.alerts.clone(),
+            audit_channel_id: config.audit.channel_id.map(ChannelId::new),
+            run: config.run.clone(),
+            permissions: config.permissions.clone(),
+            servers,
+            db_api,
+        }
+    }
+
+    pub fn server(&self, name: &str) -> Option<&Arc<Server>> {
+        self.servers.iter().find(|server| &*server.name == name)
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+
+/// The bot's local SQLite database, which holds everything that should survive restarts.
+pub struct Store {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Store {
+    /// Opens (or creates) the database at `path`, and runs each of the `schemas` on it. These should
+    /// only consist of `CREATE ... IF NOT EXISTS` statements, since they run every time.
+    pub fn open(path: impl AsRef<Path>, schemas: &[&str]) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        for schema in schemas {
+            connection.execute_batch(schema)?;
+        }
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `f` with the connection on a blocking thread.
+    pub async fn call<T, F>(&self, f: F) -> rusqlite::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || f(&connection.lock().unwrap()))
+            .await
+            .unwrap()
+    }
+}
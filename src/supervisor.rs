@@ -0,0 +1,482 @@
+//! Runs a server's process, restarting it when it exits unexpectedly.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::config::SupervisorConfig;
+
+/// How many lines of the server's output are kept.
+const OUTPUT_LINES: usize = 100;
+
+/// The delay before the first restart of a crashing server, which doubles with each crash.
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+
+/// A server which ran for this long wasn't crash looping, so the backoff starts over.
+const STABLE_UPTIME: Duration = Duration::from_secs(300);
+
+/// How long to wait for a connection when checking whether the server is running already.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Start,
+    Stop,
+    Restart,
+    Kill,
+    Status,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum State {
+    Stopped,
+    Running {
+        pid: Option<u32>,
+        since: SystemTime,
+        /// Set once `stop` was sent to the server.
+        stopping: bool,
+    },
+    /// The server exited, and will be started again at the given time.
+    Restarting {
+        at: SystemTime,
+    },
+}
+
+pub struct Status {
+    pub state: State,
+    /// The number of automatic restarts so far.
+    pub restarts: u32,
+    pub last_exit: Option<ExitStatus>,
+    /// The last lines the server wrote to stdout and stderr, oldest first.
+    pub output: Vec<String>,
+}
+
+struct Request {
+    action: Action,
+    reply: oneshot::Sender<Result<Status, Cow<'static, str>>>,
+}
+
+#[derive(Clone)]
+struct Options {
+    config: SupervisorConfig,
+    directory: PathBuf,
+    /// The server's RCON and game addresses, which are only reachable while a server is running.
+    addresses: Vec<String>,
+}
+
+/// A handle to the task which runs the server. The task stops once every handle is dropped, but
+/// leaves the server running. The server isn't started while one of its `addresses` can be
+/// connected to, since that means it's still running (e.g. from before the bot was restarted), and a
+/// second one would fail to bind its ports or to lock the world.
+pub struct Supervisor {
+    requests: mpsc::Sender<Request>,
+    options: Arc<Mutex<Options>>,
+}
+
+impl Supervisor {
+    /// Starts supervising a server, which is started right away if `config.autostart` is set.
+    pub fn new(config: &SupervisorConfig, directory: &str, addresses: &[&str]) -> Self {
+        let options = Arc::new(Mutex::new(Options {
+            config: config.clone(),
+            directory: directory.into(),
+            addresses: addresses
+                .iter()
+                .map(|&address| address.to_owned())
+                .collect(),
+        }));
+        let (requests, receiver) = mpsc::channel(8);
+
+        let task = Task {
+            options: Arc::clone(&options),
+            output: Arc::default(),
+            process: None,
+            wanted: config.autostart,
+            restart_now: false,
+            restart_at: config.autostart.then(Instant::now),
+            backoff: Duration::ZERO,
+            restarts: 0,
+            last_exit: None,
+        };
+        tokio::spawn(task.run(receiver));
+
+        Self { requests, options }
+    }
+
+    /// Replaces the config, which takes effect the next time the server is started.
+    pub fn update(&self, config: &SupervisorConfig, directory: &str, addresses: &[&str]) {
+        *self.options.lock().unwrap() = Options {
+            config: config.clone(),
+            directory: directory.into(),
+            addresses: addresses
+                .iter()
+                .map(|&address| address.to_owned())
+                .collect(),
+        };
+    }
+
+    /// Performs `action`, and returns the status afterwards. Stopping and restarting return as soon
+    /// as the server was asked to stop.
+    pub async fn request(&self, action: Action) -> Result<Status, Cow<'static, str>> {
+        let (reply, receiver) = oneshot::channel();
+        self.requests
+            .send(Request { action, reply })
+            .await
+            .map_err(|_| "The supervisor isn't running (this is a bug)")?;
+        receiver
+            .await
+            .map_err(|_| "The supervisor isn't running (this is a bug)")?
+    }
+}
+
+struct Process {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    started: Instant,
+    since: SystemTime,
+    /// When the server gets killed, if it was asked to stop.
+    kill_at: Option<Instant>,
+}
+
+struct Task {
+    options: Arc<Mutex<Options>>,
+    output: Arc<Mutex<VecDeque<String>>>,
+    process: Option<Process>,
+    /// Whether the server should be running, i.e. whether it's started again when it exits.
+    wanted: bool,
+    /// Set when the server is being restarted from discord, so it's started again even without
+    /// `auto_restart`.
+    restart_now: bool,
+    restart_at: Option<Instant>,
+    backoff: Duration,
+    restarts: u32,
+    last_exit: Option<ExitStatus>,
+}
+
+impl Task {
+    async fn run(mut self, mut requests: mpsc::Receiver<Request>) {
+        loop {
+            let kill_at = self.process.as_ref().and_then(|process| process.kill_at);
+            tokio::select! {
+                request = requests.recv() => {
+                    let Some(request) = request else {
+                        return;
+                    };
+                    let result = self.handle(request.action).await.map(|()| self.status());
+                    let _ = request.reply.send(result);
+                }
+                status = wait(&mut self.process) => self.exited(status),
+                _ = sleep_until(self.restart_at) => {
+                    self.restart_at = None;
+                    if let Some(address) = self.running_elsewhere().await {
+                        log::warn!("The server is already running (it's listening on {address}), so it isn't started");
+                        self.wanted = false;
+                    } else if let Err(why) = self.start() {
+                        log::error!("{why}");
+                        self.schedule_restart(Duration::ZERO);
+                    }
+                }
+                _ = sleep_until(kill_at) => {
+                    let process = self.process.as_mut().unwrap();
+                    process.kill_at = None;
+                    log::warn!("The server didn't stop in time, killing it");
+                    let _ = process.child.start_kill();
+                }
+            }
+        }
+    }
+
+    async fn handle(&mut self, action: Action) -> Result<(), Cow<'static, str>> {
+        match (action, &mut self.process) {
+            (Action::Status, _) => {}
+            (Action::Start, Some(_)) => return Err("The server is already running.".into()),
+            (Action::Start | Action::Restart, None) => {
+                if let Some(address) = self.running_elsewhere().await {
+                    return Err(format!(
+                        "The server is already running without the bot (it's listening on {address}), so it has to be stopped another way first."
+                    )
+                    .into());
+                }
+                self.restart_at = None;
+                self.wanted = true;
+                self.start()?;
+            }
+            (Action::Stop, None) if self.restart_at.is_some() => {
+                self.restart_at = None;
+                self.wanted = false;
+            }
+            (Action::Stop | Action::Kill, None) => return Err("The server isn't running.".into()),
+            (Action::Stop, Some(_)) => {
+                self.wanted = false;
+                self.stop().await;
+            }
+            (Action::Restart, Some(_)) => {
+                self.wanted = true;
+                self.restart_now = true;
+                self.stop().await;
+            }
+            (Action::Kill, Some(process)) => {
+                self.wanted = false;
+                process
+                    .child
+                    .start_kill()
+                    .map_err(|why| format!("Couldn't kill the server: {why}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Cow<'static, str>> {
+        let options = self.options.lock().unwrap().clone();
+        let command = &options.config.command;
+        let mut child = Command::new(command.program())
+            .args(command.args())
+            .current_dir(&options.directory)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|why| format!("Couldn't start the server: {why}"))?;
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(capture(stdout, Arc::clone(&self.output)));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(capture(stderr, Arc::clone(&self.output)));
+        }
+
+        self.process = Some(Process {
+            stdin: child.stdin.take(),
+            child,
+            started: Instant::now(),
+            since: SystemTime::now(),
+            kill_at: None,
+        });
+        Ok(())
+    }
+
+    /// Returns one of the server's addresses if something listens on it, even though the server
+    /// isn't running under this supervisor.
+    async fn running_elsewhere(&self) -> Option<String> {
+        let addresses = self.options.lock().unwrap().addresses.clone();
+        for address in addresses {
+            let connection = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&address));
+            if let Ok(Ok(_)) = connection.await {
+                return Some(address);
+            }
+        }
+        None
+    }
+
+    /// Asks the server to stop through its console, and kills it if it doesn't in time.
+    async fn stop(&mut self) {
+        let timeout = Duration::from_secs(self.options.lock().unwrap().config.stop_timeout);
+        let Some(process) = &mut self.process else {
+            return;
+        };
+        if process.kill_at.is_some() {
+            return;
+        }
+
+        let stopped = match &mut process.stdin {
+            Some(stdin) => stdin.write_all(b"stop\n").await.is_ok(),
+            None => false,
+        };
+        if stopped {
+            process.kill_at = Some(Instant::now() + timeout);
+        } else {
+            let _ = process.child.start_kill();
+        }
+    }
+
+    fn exited(&mut self, status: std::io::Result<ExitStatus>) {
+        let process = self.process.take().unwrap();
+        let uptime = process.started.elapsed();
+        match &status {
+            Ok(status) if status.success() => log::info!("The server stopped"),
+            Ok(status) => log::warn!("The server exited with {status}"),
+            Err(why) => log::error!("Couldn't wait for the server to exit: {why}"),
+        }
+        self.last_exit = status.ok();
+
+        if std::mem::take(&mut self.restart_now) {
+            self.restart_at = Some(Instant::now());
+        } else if self.wanted && self.options.lock().unwrap().config.auto_restart {
+            self.restarts += 1;
+            self.schedule_restart(uptime);
+        } else {
+            self.wanted = false;
+        }
+    }
+
+    /// Restarts the server after a delay, which grows while it keeps exiting soon after starting.
+    fn schedule_restart(&mut self, uptime: Duration) {
+        let max = Duration::from_secs(self.options.lock().unwrap().config.max_backoff);
+        self.backoff = backoff(self.backoff, uptime, max);
+        log::info!("Restarting the server in {}s", self.backoff.as_secs());
+        self.restart_at = Some(Instant::now() + self.backoff);
+    }
+
+    fn status(&self) -> Status {
+        let state = match (&self.process, self.restart_at) {
+            (Some(process), _) => State::Running {
+                pid: process.child.id(),
+                since: process.since,
+                stopping: process.kill_at.is_some(),
+            },
+            (None, Some(at)) => State::Restarting {
+                at: SystemTime::now() + at.saturating_duration_since(Instant::now()),
+            },
+            (None, None) => State::Stopped,
+        };
+        Status {
+            state,
+            restarts: self.restarts,
+            last_exit: self.last_exit,
+            output: self.output.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+/// Returns the delay before the next restart, given the previous one and how long the server ran.
+fn backoff(previous: Duration, uptime: Duration, max: Duration) -> Duration {
+    if uptime >= STABLE_UPTIME || previous.is_zero() {
+        MIN_BACKOFF.min(max)
+    } else {
+        (previous * 2).min(max)
+    }
+}
+
+async fn wait(process: &mut Option<Process>) -> std::io::Result<ExitStatus> {
+    match process {
+        Some(process) => process.child.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Keeps the last lines of `output`, and logs them at the debug level.
+async fn capture(output: impl AsyncRead + Unpin, lines: Arc<Mutex<VecDeque<String>>>) {
+    let mut output = BufReader::new(output).lines();
+    while let Ok(Some(line)) = output.next_line().await {
+        log::debug!(target: "server", "{line}");
+        let mut lines = lines.lock().unwrap();
+        if lines.len() == OUTPUT_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommandLine;
+
+    #[test]
+    fn backoffs() {
+        let max = Duration::from_secs(60);
+        let crash = Duration::from_secs(1);
+
+        let mut delay = Duration::ZERO;
+        let mut delays = vec![];
+        for _ in 0..6 {
+            delay = backoff(delay, crash, max);
+            delays.push(delay.as_secs());
+        }
+        assert_eq!(delays, [5, 10, 20, 40, 60, 60]);
+        assert_eq!(backoff(delay, STABLE_UPTIME, max), MIN_BACKOFF);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stopping_and_starting() {
+        // a "server" which stops when told to
+        let config = SupervisorConfig {
+            command: CommandLine::new(
+                ["sh", "-c", "echo started; read line; echo \"got $line\""]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .unwrap(),
+            autostart: false,
+            auto_restart: true,
+            stop_timeout: 10,
+            max_backoff: 300,
+        };
+        let supervisor = Supervisor::new(&config, ".", &[]);
+
+        let status = supervisor.request(Action::Status).await.unwrap();
+        assert_eq!(status.state, State::Stopped);
+        assert!(supervisor.request(Action::Stop).await.is_err());
+
+        let status = supervisor.request(Action::Start).await.unwrap();
+        assert!(matches!(status.state, State::Running { .. }));
+        assert!(supervisor.request(Action::Start).await.is_err());
+
+        let status = supervisor.request(Action::Stop).await.unwrap();
+        assert!(matches!(
+            status.state,
+            State::Running { stopping: true, .. }
+        ));
+
+        let status = loop {
+            let status = supervisor.request(Action::Status).await.unwrap();
+            // the output is read separately, so it may still be on its way
+            if status.state == State::Stopped && status.output.len() == 2 {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert!(status.last_exit.unwrap().success());
+        assert_eq!(status.restarts, 0);
+        assert_eq!(status.output, ["started", "got stop"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn already_running() {
+        // the "running" server
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let config = SupervisorConfig {
+            command: CommandLine::new(["echo", "started"].map(String::from).to_vec()).unwrap(),
+            autostart: true,
+            auto_restart: true,
+            stop_timeout: 10,
+            max_backoff: 300,
+        };
+        let supervisor = Supervisor::new(&config, ".", &[&address]);
+
+        // the autostart happens right away, but it may not have yet
+        let status = loop {
+            let status = supervisor.request(Action::Status).await.unwrap();
+            if !matches!(status.state, State::Restarting { .. }) {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(status.state, State::Stopped);
+        assert_eq!(status.last_exit, None);
+        assert!(supervisor.request(Action::Start).await.is_err());
+
+        drop(listener);
+        let status = supervisor.request(Action::Start).await.unwrap();
+        assert!(matches!(status.state, State::Running { .. }));
+    }
+}